@@ -0,0 +1,119 @@
+use crate::lexer::{Token, TokenKind, tokens};
+
+/// Error raised by formulas that used up their step budget
+pub const TIMED_OUT: &str = "evaluation timed out";
+
+/// Global of the cell's Lua state counting the steps taken by its formula
+const STEPS: &str = "__bight_steps";
+
+/// Makes the formula `source` raise an "evaluation timed out" error once it took more than `limit`
+/// steps. A step is an iteration of a loop, a call of a function defined in the formula or a
+/// `goto`. Every cell is evaluated in its own Lua state, so the budget is per cell.
+///
+/// bight gives formulas no debug library to set a hook with, so the steps are counted by a
+/// statement inserted at the start of every loop body and function body and before every `goto`.
+/// Nothing is inserted after labels: a label ending a block may be jumped to over local
+/// declarations (the `continue` idiom), which a statement after it would forbid.
+pub fn limit_steps(source: &str, limit: u64) -> String {
+    let step = format!(
+        " do local steps = (rawget(_G, \"{STEPS}\") or 0) + 1 rawset(_G, \"{STEPS}\", steps) \
+         if steps > {limit} then error(\"{TIMED_OUT}\", 0) end end "
+    );
+    let mut limited = String::with_capacity(source.len());
+    let mut copied = 0;
+    for at in step_positions(source) {
+        limited.push_str(&source[copied..at]);
+        limited.push_str(&step);
+        copied = at;
+    }
+    limited.push_str(&source[copied..]);
+    limited
+}
+
+/// Offsets in `source` where a step is counted: after `do` and `repeat`, after the parameters of
+/// a function and before `goto`
+fn step_positions(source: &str) -> Vec<usize> {
+    let tokens: Vec<Token> = tokens(source)
+        .into_iter()
+        .filter(|token| token.kind != TokenKind::Space)
+        .collect();
+    let is_keyword = |i: usize, keyword: &str| {
+        tokens[i].kind == TokenKind::Name && tokens[i].text(source) == keyword
+    };
+
+    let mut positions = Vec::new();
+    let mut parameters = false;
+    for (i, token) in tokens.iter().enumerate() {
+        if is_keyword(i, "do") || is_keyword(i, "repeat") {
+            positions.push(token.end);
+        } else if is_keyword(i, "goto") {
+            positions.push(token.start);
+        } else if is_keyword(i, "function") {
+            parameters = true;
+        } else if parameters && token.text(source) == ")" {
+            parameters = false;
+            positions.push(token.end);
+        }
+    }
+    positions
+}
+
+#[cfg(test)]
+mod test {
+    use bight::{
+        evaluator::{EvaluatorTable, SourceTable},
+        table::{Table, cell::CellPos},
+    };
+
+    use super::*;
+
+    fn evaluate(source: &str, limit: u64) -> String {
+        let pos = CellPos::from((0, 0));
+        let mut sources = SourceTable::new();
+        sources.insert(pos, limit_steps(source, limit).into());
+        let mut table = EvaluatorTable::new(sources);
+        table.evaluate();
+        table.get(pos).unwrap().to_string()
+    }
+
+    #[test]
+    fn positions() {
+        let source =
+            "=(function(n) ::top:: for i = 1, n do end repeat goto top until true end)(2) -- do";
+        let after: Vec<&str> = step_positions(source)
+            .into_iter()
+            .map(|at| &source[..at])
+            .collect();
+        assert_eq!(
+            after,
+            vec![
+                "=(function(n)",
+                "=(function(n) ::top:: for i = 1, n do",
+                "=(function(n) ::top:: for i = 1, n do end repeat",
+                "=(function(n) ::top:: for i = 1, n do end repeat ",
+            ]
+        );
+        assert_eq!(step_positions("=\"do\" .. a:b(1)"), Vec::<usize>::new());
+    }
+
+    #[test]
+    fn limited() {
+        assert!(evaluate("=while true do end", 1000).contains(TIMED_OUT));
+        assert!(
+            evaluate(
+                "=(function(f) return f(f) end)(function(f) return f(f) end)",
+                1000
+            )
+            .contains(TIMED_OUT)
+        );
+        let sum = "=(function() local s = 0 for i = 1, 10 do s = s + i end return s end)()";
+        assert_eq!(evaluate(sum, 1000), "55");
+        assert!(evaluate(sum, 5).contains(TIMED_OUT));
+        assert_eq!(evaluate("=1 + 2", 1), "3");
+        assert!(evaluate("=(function() ::top:: goto top end)()", 1000).contains(TIMED_OUT));
+        let continued = "=(function() local s = 0 for i = 1, 4 do \
+            if i % 2 == 0 then goto continue end local v = i * 2 s = s + v ::continue:: \
+            end return s end)()";
+        assert_eq!(evaluate(continued, 1000), "8");
+    }
+}
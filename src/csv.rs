@@ -0,0 +1,251 @@
+use std::{
+    path::Path,
+    sync::{Arc, LazyLock, Mutex},
+};
+
+use bight::evaluator::SourceTable;
+use nvim_oxi::{Dictionary, ObjectKind};
+
+use crate::util::{get_as_dictionary, notify_err};
+
+/// Whether the first row of a .csv file is a header
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HeaderMode {
+    Auto,
+    Always,
+    Never,
+}
+
+/// What is written to a .csv file for each cell
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WriteMode {
+    Sources,
+    Values,
+}
+
+#[derive(Debug, Clone)]
+pub struct CsvOptions {
+    pub delimiter: char,
+    pub quote: char,
+    pub header: HeaderMode,
+    pub write: WriteMode,
+}
+
+impl Default for CsvOptions {
+    fn default() -> Self {
+        Self {
+            delimiter: ',',
+            quote: '"',
+            header: HeaderMode::Auto,
+            write: WriteMode::Sources,
+        }
+    }
+}
+
+static CSV_OPTIONS: LazyLock<Mutex<CsvOptions>> =
+    LazyLock::new(|| Mutex::new(CsvOptions::default()));
+
+pub fn csv_options() -> CsvOptions {
+    CSV_OPTIONS.lock().unwrap().clone()
+}
+
+/// Applies the `csv` table from setup options: `delimiter` and `quote` (single characters),
+/// `header` (`"auto"`, `true` or `false`) and `write` (`"sources"` or `"values"`).
+pub fn configure_csv(opts: &Dictionary) {
+    let Some(csv) = get_as_dictionary(opts, "csv") else {
+        return;
+    };
+    let mut options = CSV_OPTIONS.lock().unwrap();
+    for (key, value) in csv.iter() {
+        let key = key.to_string_lossy();
+        let string = match value.kind() {
+            ObjectKind::String => Some(unsafe { value.as_nvim_str_unchecked() }.to_string()),
+            _ => None,
+        };
+        let char = string.as_deref().and_then(|s| {
+            let mut chars = s.chars();
+            chars.next().filter(|_| chars.next().is_none())
+        });
+        match (key.as_ref(), char, string.as_deref()) {
+            ("delimiter", Some(c), _) => options.delimiter = c,
+            ("quote", Some(c), _) => options.quote = c,
+            ("header", _, Some("auto")) => options.header = HeaderMode::Auto,
+            ("header", _, None) if value.kind() == ObjectKind::Boolean => {
+                options.header = if unsafe { value.as_boolean_unchecked() } {
+                    HeaderMode::Always
+                } else {
+                    HeaderMode::Never
+                }
+            }
+            ("write", _, Some("sources")) => options.write = WriteMode::Sources,
+            ("write", _, Some("values")) => options.write = WriteMode::Values,
+            _ => notify_err(&format!("Invalid bight csv option {key}")),
+        }
+    }
+}
+
+pub fn is_csv(path: &Path) -> bool {
+    path.extension()
+        .and_then(|ext| ext.to_str())
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("csv"))
+}
+
+/// Splits csv text into records. Quoted fields may contain delimiters, newlines and doubled quotes.
+pub fn parse(text: &str, options: &CsvOptions) -> Vec<Vec<String>> {
+    let mut records = Vec::new();
+    let mut record = Vec::new();
+    let mut field = String::new();
+    let mut quoted = false;
+    let mut chars = text.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        if quoted {
+            if c == options.quote {
+                if chars.peek() == Some(&options.quote) {
+                    field.push(c);
+                    chars.next();
+                } else {
+                    quoted = false;
+                }
+            } else {
+                field.push(c);
+            }
+        } else if c == options.quote && field.is_empty() {
+            quoted = true;
+        } else if c == options.delimiter {
+            record.push(std::mem::take(&mut field));
+        } else if c == '\n' || c == '\r' {
+            if c == '\r' && chars.peek() == Some(&'\n') {
+                chars.next();
+            }
+            record.push(std::mem::take(&mut field));
+            records.push(std::mem::take(&mut record));
+        } else {
+            field.push(c);
+        }
+    }
+    if !field.is_empty() || !record.is_empty() {
+        record.push(field);
+        records.push(record);
+    }
+    records
+}
+
+/// Guesses whether the first record is a header: all of its fields are non-empty text while the
+/// second record has at least one number.
+pub fn detect_header(records: &[Vec<String>]) -> bool {
+    let is_number = |field: &String| field.trim().parse::<f64>().is_ok();
+    match records {
+        [first, second, ..] => {
+            !first.is_empty()
+                && first.iter().all(|f| !f.is_empty() && !is_number(f))
+                && second.iter().any(is_number)
+        }
+        _ => false,
+    }
+}
+
+fn escape_literal(field: &str) -> String {
+    if field.starts_with('=') || field.starts_with('\\') {
+        format!("\\{field}")
+    } else {
+        field.to_string()
+    }
+}
+
+/// Loads a .csv file as a table of sources. Header fields are always loaded as literal text.
+pub fn load(path: &Path, options: &CsvOptions) -> std::io::Result<SourceTable> {
+    let text = std::fs::read_to_string(path)?;
+    let records = parse(&text, options);
+    let header = match options.header {
+        HeaderMode::Auto => detect_header(&records),
+        HeaderMode::Always => true,
+        HeaderMode::Never => false,
+    };
+
+    let mut table = SourceTable::new();
+    for (y, record) in records.iter().enumerate() {
+        for (x, field) in record.iter().enumerate() {
+            if field.is_empty() {
+                continue;
+            }
+            let source = if header && y == 0 {
+                escape_literal(field)
+            } else {
+                field.clone()
+            };
+            table.insert((x as isize, y as isize).into(), Arc::<str>::from(source));
+        }
+    }
+    Ok(table)
+}
+
+fn quote_field(field: &str, options: &CsvOptions) -> String {
+    let needs_quotes = field.contains(options.delimiter)
+        || field.contains(options.quote)
+        || field.contains('\n')
+        || field.contains('\r');
+    if needs_quotes {
+        let quote = options.quote.to_string();
+        let doubled = quote.repeat(2);
+        format!("{quote}{}{quote}", field.replace(&quote, &doubled))
+    } else {
+        field.to_string()
+    }
+}
+
+/// Formats records as csv text
+pub fn write(records: &[Vec<String>], options: &CsvOptions) -> String {
+    records
+        .iter()
+        .map(|record| {
+            record
+                .iter()
+                .map(|field| quote_field(field, options))
+                .collect::<Vec<_>>()
+                .join(&options.delimiter.to_string())
+                + "\n"
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn parse_quoted() {
+        let options = CsvOptions::default();
+        let records = parse("a,\"b,c\",\"d\"\"e\"\n1,\"2\n3\",\r\n", &options);
+        assert_eq!(
+            records,
+            vec![
+                vec!["a".to_string(), "b,c".to_string(), "d\"e".to_string()],
+                vec!["1".to_string(), "2\n3".to_string(), String::new()],
+            ]
+        );
+    }
+
+    #[test]
+    fn write_roundtrip() {
+        let options = CsvOptions {
+            delimiter: ';',
+            ..Default::default()
+        };
+        let records = vec![
+            vec!["a;b".to_string(), "\"q\"".to_string()],
+            vec![String::new(), "x".to_string()],
+        ];
+        let text = write(&records, &options);
+        assert_eq!(text, "\"a;b\";\"\"\"q\"\"\"\n;x\n");
+        assert_eq!(parse(&text, &options), records);
+    }
+
+    #[test]
+    fn header_detection() {
+        let records = parse("name,price\napple,3\n", &CsvOptions::default());
+        assert!(detect_header(&records));
+        let records = parse("1,2\n3,4\n", &CsvOptions::default());
+        assert!(!detect_header(&records));
+    }
+}
@@ -1,10 +1,24 @@
 mod api;
 mod autocmd;
+mod background;
 mod clipboard;
+mod dependency;
+mod diagnostic;
+mod header;
+mod history;
 mod key;
+mod search;
+mod viewport;
 pub use autocmd::attach_editor_autocmd;
+use background::ShownValues;
+pub use background::configure_evaluation;
+pub use dependency::{configure_dependency_marks, draw_dependency_marks};
 use hashbrown::HashMap;
-pub use key::add_keymaps;
+pub use header::{
+    cell_info, configure_headers, create_header_highlights, draw_headers, statuscolumn,
+};
+pub use key::{add_keymaps, configure_keymaps};
+pub use viewport::{ColumnWidths, Viewport};
 
 use std::{
     ops::Deref,
@@ -14,7 +28,7 @@ use std::{
 
 use bight::{
     clipboard::Clipboard,
-    evaluator::{EvaluatorTable, SourceTable, TableValue},
+    evaluator::{EvaluatorTable, SourceTable, TableValue, ValueTable},
     file::slice_to_csv_string,
     table::{CellRange, Table, cell::CellPos, slice::row::RowSlice},
 };
@@ -26,41 +40,385 @@ use nvim_oxi::{
     },
 };
 
-use crate::util::{self, cursor_position, get_buffer_line};
+use crate::{
+    budget::limit_steps,
+    csv,
+    editor::{
+        clipboard::{Block, NvimClipboard},
+        history::{CellChange, Change, History},
+    },
+    reference::{
+        Axis, find_cycles, format_cycle, move_pos, move_references, references, shift_references,
+        strip_anchors,
+    },
+    series,
+    sort::{self, SortKey},
+    util::{self, cursor_position, get_buffer_line, window_size},
+};
 
 pub struct EditorState {
     edit: Option<CellPos>,
     visual_start: CellPos,
     buffer: Buffer,
+    /// Sources of the cells as written by the user
+    sources: SourceTable,
+    /// The evaluated table. Its sources are the `sources` prepared with `evaluation_source`.
     table: EvaluatorTable,
     clipboard: Clipboard,
+    history: History,
+    viewport: Viewport,
+    /// Cells found by the last search
+    matches: Vec<CellPos>,
+    /// Quickfix and location lists of cells made by the editor by their ids
+    cell_lists: HashMap<u64, search::CellList>,
+    /// Reference cycles found by the last `update_cycles`
+    cycles: Vec<Vec<CellPos>>,
+    /// Number of cells with errors outside of the view at the last `draw_errors`
+    hidden_errors: usize,
+    /// `generation` the cycles were looked for at
+    cycles_generation: u64,
+    /// Whether sources were changed since the last evaluation
+    dirty: bool,
+    /// Incremented on every change of the sources
+    generation: u64,
+    /// The values last evaluated in the background, the evaluation sources they were evaluated
+    /// from and the `generation` of those sources
+    values: ValueTable,
+    values_sources: SourceTable,
+    values_generation: u64,
+    /// The thread evaluating the table in the background, started by the first background
+    /// evaluation
+    worker: Option<background::Worker>,
+    /// Whether a timer is waiting for `worker`
+    polling: bool,
+    /// Cells whose background evaluation was given up and why, until their sources change
+    cancelled: HashMap<CellPos, &'static str>,
 }
 
 impl EditorState {
     pub fn with_new_buffer(buffer: Buffer) -> Self {
+        Self::with_sources(buffer, SourceTable::new())
+    }
+    pub fn with_file_buffer(buffer: Buffer, file: &Path) -> anyhow::Result<Self> {
+        let sources = if csv::is_csv(file) {
+            csv::load(file, &csv::csv_options())?
+        } else {
+            bight::file::load(file)?.source
+        };
+        Ok(Self::with_sources(buffer, sources))
+    }
+    fn with_sources(buffer: Buffer, sources: SourceTable) -> Self {
+        let table = EvaluatorTable::new(
+            sources
+                .iter()
+                .map(|(&pos, source)| (pos, evaluation_source(source)))
+                .collect(),
+        );
         Self {
             buffer,
             edit: None,
             visual_start: CellPos::default(),
-            table: EvaluatorTable::new(SourceTable::new()),
-            clipboard: Clipboard::new(),
+            sources,
+            table,
+            clipboard: Clipboard::with_provider(NvimClipboard),
+            history: History::new(),
+            viewport: Viewport::default(),
+            matches: Vec::new(),
+            cell_lists: HashMap::new(),
+            cycles: Vec::new(),
+            hidden_errors: 0,
+            cycles_generation: 0,
+            dirty: true,
+            generation: 1,
+            values: ValueTable::new(),
+            values_sources: SourceTable::new(),
+            values_generation: 0,
+            worker: None,
+            polling: false,
+            cancelled: HashMap::new(),
         }
     }
-    pub fn with_file_buffer(buffer: Buffer, file: &Path) -> anyhow::Result<Self> {
-        let source = bight::file::load(file)?.source;
-        Ok(Self {
-            buffer,
-            edit: None,
-            visual_start: CellPos::default(),
-            table: EvaluatorTable::new(source),
-            clipboard: Clipboard::new(),
-        })
+    pub fn get_source(&self, pos: impl Into<CellPos>) -> Option<&Arc<str>> {
+        self.sources.get(&pos.into())
+    }
+    /// Sets the source of the cell and records the change in the pending history step. The step
+    /// must be finished with `commit_history` to become undoable. Does nothing if the source does
+    /// not change, so the cell and its dependents keep their values.
+    pub fn set_source(&mut self, pos: CellPos, src: Option<Arc<str>>) {
+        let old = self.get_source(pos).cloned();
+        if old.as_deref().unwrap_or("") == src.as_deref().unwrap_or("") {
+            return;
+        }
+        self.history.record(Change::Cell(CellChange {
+            pos,
+            old,
+            new: src.clone(),
+        }));
+        self.write_source(pos, src);
+    }
+    /// Sets the source of the cell without recording it in the history
+    fn write_source(&mut self, pos: CellPos, src: Option<Arc<str>>) {
+        let evaluated = src.as_ref().map(evaluation_source);
+        if let Some(worker) = &mut self.worker {
+            worker.record(pos, evaluated.clone());
+        }
+        self.cancelled.remove(&pos);
+        self.table.set_source(pos, evaluated);
+        match src {
+            Some(src) => self.sources.insert(pos, src),
+            None => self.sources.remove(&pos),
+        };
+        self.mark_changed();
+    }
+    fn mark_changed(&mut self) {
+        self.dirty = true;
+        self.generation += 1;
     }
+    /// Evaluates the cells whose sources or dependencies changed since the last evaluation.
+    /// Returns false without touching the table if no sources were changed.
+    pub fn evaluate(&mut self) -> bool {
+        if !self.dirty {
+            return false;
+        }
+        self.table.evaluate();
+        self.dirty = false;
+        true
+    }
+    /// Pastes the block with its top-left cell at `pos`. References in formulas are shifted by the
+    /// offset between `pos` and the block's origin.
+    pub fn paste_block(&mut self, pos: CellPos, block: &Block) {
+        let (shift_x, shift_y) = (pos.x - block.origin.x, pos.y - block.origin.y);
+        for (dy, row) in block.sources.iter().enumerate() {
+            for (dx, source) in row.iter().enumerate() {
+                let target = CellPos::from((pos.x + dx as isize, pos.y + dy as isize));
+                let source = source
+                    .as_deref()
+                    .map(|source| Arc::from(shift_references(source, shift_x, shift_y)));
+                self.set_source(target, source);
+            }
+        }
+    }
+    /// Pastes the clipboard at `pos`. A yanked block is pasted with its top-left cell at `pos`,
+    /// any other text is pasted into the cell.
+    pub fn paste(&mut self, pos: CellPos) {
+        let source = self.clipboard.get();
+        match source.as_deref().and_then(clipboard::yanked_block) {
+            Some(block) => self.paste_block(pos, &block),
+            None => self.set_source(pos, source),
+        }
+    }
+    /// Inserts `count` rows or columns before `at`, or deletes them starting from `at` if `count`
+    /// is negative. References in formulas are rewritten to keep pointing at the same cells.
+    pub fn shift_cells(&mut self, axis: Axis, at: isize, count: isize) {
+        let old = self.sources.clone();
+        let mut new = SourceTable::new();
+        for (&pos, source) in old.iter() {
+            if let Some(pos) = move_pos(pos, axis, at, count) {
+                let source = move_references(source, axis, at, count);
+                new.insert(pos, Arc::from(source));
+            }
+        }
+        for &pos in old.keys() {
+            if !new.contains_key(&pos) {
+                self.set_source(pos, None);
+            }
+        }
+        for (pos, source) in new {
+            if old.get(&pos) != Some(&source) {
+                self.set_source(pos, Some(source));
+            }
+        }
+        if axis == Axis::Column {
+            let old = self.viewport.widths.clone();
+            self.viewport.widths.shift(at, count);
+            if self.viewport.widths != old {
+                let new = self.viewport.widths.clone();
+                self.history.record(Change::Widths { old, new });
+            }
+        }
+    }
+    /// Fills the range along `axis` from its first cells: each column is filled down from the
+    /// first row for `Axis::Row`, each row is filled right from the first column for
+    /// `Axis::Column`, shifting references. If a column (row) starts with at least two cells
+    /// forming a numeric or date series and is empty after them, the series is extended instead.
+    pub fn fill(&mut self, range: CellRange, axis: Axis) {
+        let (lanes, len) = match axis {
+            Axis::Row => (range.columns(), range.height),
+            Axis::Column => (range.rows(), range.width),
+        };
+        let cell = |lane: isize, i: usize| match axis {
+            Axis::Row => CellPos::from((range.start.x + lane, range.start.y + i as isize)),
+            Axis::Column => CellPos::from((range.start.x + i as isize, range.start.y + lane)),
+        };
+        for lane in lanes {
+            let sources: Vec<&str> = (0..len)
+                .map(|i| self.get_source(cell(lane, i)).map_or("", |source| source))
+                .collect();
+            let (start, filled) = fill_lane(&sources, axis);
+            for (i, source) in (start..).zip(filled) {
+                let source = (!source.is_empty()).then(|| Arc::from(source));
+                self.set_source(cell(lane, i), source);
+            }
+        }
+    }
+    /// Reorders the rows of the range by the evaluated values of the key columns. The sort is
+    /// stable, and references in the moved formulas are shifted as when pasting.
+    pub fn sort(&mut self, range: CellRange, keys: &[SortKey]) {
+        self.evaluate();
+        let mut rows: Vec<(isize, Vec<TableValue>)> = range
+            .rows()
+            .map(|row| {
+                let y = range.start.y + row;
+                let values = keys
+                    .iter()
+                    .map(|key| {
+                        self.table
+                            .get((key.x, y).into())
+                            .cloned()
+                            .unwrap_or(TableValue::Empty)
+                    })
+                    .collect();
+                (y, values)
+            })
+            .collect();
+        rows.sort_by(|(_, a), (_, b)| sort::compare_rows(keys, a, b));
+
+        let sources: Vec<Vec<Option<Arc<str>>>> = rows
+            .iter()
+            .map(|&(y, _)| {
+                range
+                    .columns()
+                    .map(|col| self.get_source((range.start.x + col, y)).cloned())
+                    .collect()
+            })
+            .collect();
+        for (row, (&(old_y, _), sources)) in rows.iter().zip(sources).enumerate() {
+            let y = range.start.y + row as isize;
+            for (col, source) in sources.into_iter().enumerate() {
+                let source =
+                    source.map(|source| Arc::from(shift_references(&source, 0, y - old_y)));
+                self.set_source((range.start.x + col as isize, y).into(), source);
+            }
+        }
+    }
+    /// Looks for reference cycles between the formulas of the table if the sources changed since
+    /// the previous call. Returns the cycles that were not found by the previous call.
+    pub fn update_cycles(&mut self) -> Vec<Vec<CellPos>> {
+        if self.cycles_generation == self.generation {
+            return Vec::new();
+        }
+        self.cycles_generation = self.generation;
+        let edges: HashMap<CellPos, Vec<CellPos>> = self
+            .sources
+            .iter()
+            .filter(|(_, source)| source.starts_with('='))
+            .map(|(&pos, source)| (pos, references(source)))
+            .collect();
+        let cycles = find_cycles(&edges);
+        let new = cycles
+            .iter()
+            .filter(|cycle| !self.cycles.contains(cycle))
+            .cloned()
+            .collect();
+        self.cycles = cycles;
+        new
+    }
+    /// The cycle the cell is part of, if any
+    pub fn cycle_of(&self, pos: CellPos) -> Option<&[CellPos]> {
+        self.cycles
+            .iter()
+            .find(|cycle| cycle.contains(&pos))
+            .map(|cycle| cycle.as_slice())
+    }
+    pub fn commit_history(&mut self) {
+        self.history.commit();
+    }
+    /// Reverts the last history step. Returns `None` if there is nothing to undo, otherwise the
+    /// position of the first cell changed by the step if it changed any.
+    pub fn undo(&mut self) -> Option<Option<CellPos>> {
+        let changes = self.history.undo()?;
+        for change in changes.iter() {
+            match change {
+                Change::Cell(change) => self.write_source(change.pos, change.old.clone()),
+                Change::Widths { old, .. } => self.viewport.widths = old.clone(),
+            }
+        }
+        self.mark_changed();
+        Some(changed_cells(&changes).last().copied())
+    }
+    /// Reapplies the last undone history step. Returns `None` if there is nothing to redo,
+    /// otherwise the position of the first cell changed by the step if it changed any.
+    pub fn redo(&mut self) -> Option<Option<CellPos>> {
+        let changes = self.history.redo()?;
+        for change in changes.iter() {
+            match change {
+                Change::Cell(change) => self.write_source(change.pos, change.new.clone()),
+                Change::Widths { new, .. } => self.viewport.widths = new.clone(),
+            }
+        }
+        self.mark_changed();
+        Some(changed_cells(&changes).first().copied())
+    }
+}
+
+fn check_count(count: isize) -> bool {
+    if count <= 0 {
+        util::notify_err(&format!("Count must be positive, got {count}"));
+    }
+    count > 0
+}
+
+/// The source given to the evaluator for the user's `source`. Anchors are removed from formulas,
+/// and formulas are limited to `evaluation_limit` steps.
+fn evaluation_source(source: &Arc<str>) -> Arc<str> {
+    if !source.starts_with('=') {
+        return source.clone();
+    }
+    let mut source = source.clone();
+    if source.contains('$') {
+        source = Arc::from(strip_anchors(&source));
+    }
+    if let Some(limit) = background::evaluation_limit() {
+        source = Arc::from(limit_steps(&source, limit));
+    }
+    source
+}
+
+/// New sources of a column (row) filled along `axis`, see `EditorState::fill`, and the index of
+/// the first cell they are written to
+fn fill_lane(sources: &[&str], axis: Axis) -> (usize, Vec<String>) {
+    let count = sources
+        .iter()
+        .position(|source| source.is_empty())
+        .unwrap_or(sources.len());
+    if count < sources.len()
+        && sources[count..].iter().all(|source| source.is_empty())
+        && let Some(series) = series::extend(&sources[..count], sources.len() - count)
+    {
+        return (count, series);
+    }
+    let seed = sources.first().copied().unwrap_or("");
+    let filled = (1..sources.len() as isize)
+        .map(|i| match axis {
+            Axis::Row => shift_references(seed, 0, i),
+            Axis::Column => shift_references(seed, i, 0),
+        })
+        .collect();
+    (1, filled)
+}
+
+fn changed_cells(changes: &[Change]) -> Vec<CellPos> {
+    changes
+        .iter()
+        .filter_map(|change| match change {
+            Change::Cell(change) => Some(change.pos),
+            Change::Widths { .. } => None,
+        })
+        .collect()
 }
 
 pub const CELL_WIDTH: usize = 8;
 pub const CELL_SEPARATOR: &str = " ";
-pub const CELL_UNIT_WIDTH: usize = CELL_WIDTH + CELL_SEPARATOR.len();
 
 static EDITORS: LazyLock<Mutex<HashMap<i32, Editor>>> =
     LazyLock::new(|| Mutex::new(HashMap::new()));
@@ -111,7 +469,7 @@ impl Editor {
 
         util::set_cursor_to_cell_pos(pos);
 
-        let (line, col) = cursor_position(pos);
+        let (line, col) = cursor_position(&self.viewport(), pos);
 
         let replace_start_x = col;
         let row = line - 1;
@@ -125,7 +483,9 @@ impl Editor {
             .trim()
             .to_string();
 
-        self.state().table.set_source(pos, Some(Arc::from(source)));
+        let mut state = self.state();
+        state.set_source(pos, Some(Arc::from(source)));
+        state.commit_history();
     }
     pub fn set_visual_start(&self, pos: CellPos) {
         self.state().visual_start = pos;
@@ -139,16 +499,172 @@ impl Editor {
         render_buffer(self);
     }
 
+    pub fn viewport(&self) -> Viewport {
+        self.state().viewport.clone()
+    }
+    /// Scrolls the viewport so that `pos` is visible, rerendering the buffer if it moved
+    pub fn scroll_to(&self, pos: CellPos) {
+        let (width, height) = window_size();
+        let scrolled = self.state().viewport.scroll_to(pos, width, height);
+        if scrolled {
+            self.render();
+        }
+    }
+    pub fn column_width(&self, x: isize) -> usize {
+        self.state().viewport.widths.get(x)
+    }
+    pub fn set_column_width(&self, x: isize, width: usize) {
+        let pos = util::current_cell_pos();
+        self.state().viewport.widths.set(x, width);
+        self.render();
+        util::set_cursor_to_cell_pos(pos);
+    }
+    pub fn resize_current_column(&self, delta: isize) {
+        let x = util::current_cell_pos().x;
+        let width = self.column_width(x).saturating_add_signed(delta);
+        self.set_column_width(x, width);
+    }
+    /// Sets the column's width to the length of its longest value
+    pub fn fit_column(&self, x: isize) {
+        let width = {
+            let mut state = self.state();
+            state.evaluate();
+            state
+                .sources
+                .keys()
+                .filter(|pos| pos.x == x)
+                .filter_map(|&pos| state.table.get(pos))
+                .map(|value| {
+                    value
+                        .to_string()
+                        .lines()
+                        .next()
+                        .unwrap_or("")
+                        .chars()
+                        .count()
+                })
+                .max()
+                .unwrap_or(CELL_WIDTH)
+        };
+        self.set_column_width(x, width);
+    }
+    pub fn fit_current_column(&self) {
+        self.fit_column(util::current_cell_pos().x);
+    }
+    /// Inserts `count` rows or columns before `at` (see `EditorState::shift_cells`). Reports an
+    /// error if `count` is not positive.
+    pub fn insert(&self, axis: Axis, at: isize, count: isize) {
+        if check_count(count) {
+            self.shift_cells(axis, at, count);
+        }
+    }
+    /// Deletes `count` rows or columns starting from `at` (see `EditorState::shift_cells`).
+    /// Reports an error if `count` is not positive.
+    pub fn delete(&self, axis: Axis, at: isize, count: isize) {
+        if check_count(count) {
+            self.shift_cells(axis, at, -count);
+        }
+    }
+    fn shift_cells(&self, axis: Axis, at: isize, count: isize) {
+        let pos = util::current_cell_pos();
+        {
+            let mut state = self.state();
+            state.shift_cells(axis, at, count);
+            state.commit_history();
+        }
+        self.render();
+        util::set_cursor_to_cell_pos(pos);
+    }
+    /// Fills the current visual range down (`Axis::Row`) or right (`Axis::Column`), see
+    /// `EditorState::fill`
+    pub fn fill_current_visual_range(&self, axis: Axis) {
+        let range = self.get_current_visual_range();
+        {
+            let mut state = self.state();
+            state.fill(range, axis);
+            state.commit_history();
+        }
+        self.render();
+    }
+    /// Sorts the rows of the range (see `EditorState::sort`) as a single undoable change
+    pub fn sort(&self, range: CellRange, keys: &[SortKey]) {
+        {
+            let mut state = self.state();
+            state.sort(range, keys);
+            state.commit_history();
+        }
+        self.render();
+    }
+    /// Sorts the rows of the current visual range by its first column
+    pub fn sort_current_visual_range(&self, descending: bool) {
+        let range = self.get_current_visual_range();
+        let key = SortKey {
+            x: range.start.x,
+            descending,
+        };
+        self.sort(range, &[key]);
+    }
+    /// Moves the viewport and the cursor by `dy` rows, keeping the cursor at the same line of the
+    /// window if possible
+    pub fn scroll_rows(&self, dy: isize) {
+        let mut pos = util::current_cell_pos();
+        {
+            let mut state = self.state();
+            let mut origin = state.viewport.origin;
+            origin.y += dy;
+            state.viewport.set_origin(origin);
+        }
+        pos.y = (pos.y + dy).max(0);
+        self.render();
+        util::set_cursor_to_cell_pos(pos);
+    }
+    /// Scrolls the viewport so that the current cell's row is `line` rows from the top of the
+    /// window
+    pub fn scroll_current_row_to(&self, line: isize) {
+        let pos = util::current_cell_pos();
+        {
+            let mut state = self.state();
+            let mut origin = state.viewport.origin;
+            origin.y = pos.y - line;
+            state.viewport.set_origin(origin);
+        }
+        self.render();
+        util::set_cursor_to_cell_pos(pos);
+    }
+
+    /// The shown value of the cell, see `EditorState::shown_values`
     pub fn get_value(&self, pos: CellPos) -> String {
-        self.state()
+        let mut state = self.state();
+        state.update_values();
+        state
+            .shown_values()
+            .get(pos)
+            .unwrap_or(&TableValue::Empty)
+            .to_string()
+    }
+    /// The value of the cell after evaluating the table, even if evaluation is asynchronous
+    pub fn get_evaluated_value(&self, pos: CellPos) -> String {
+        let mut state = self.state();
+        state.evaluate();
+        state
             .table
             .get(pos)
             .unwrap_or(&TableValue::Empty)
             .to_string()
     }
+    pub fn evaluate(&self) {
+        self.state().update_values();
+    }
+    pub fn is_err(&self, pos: CellPos) -> bool {
+        let mut state = self.state();
+        state.update_values();
+        state
+            .shown_values()
+            .get(pos)
+            .is_some_and(TableValue::is_err)
+    }
     pub fn get_source(&self, pos: CellPos) -> String {
         self.state()
-            .table
             .get_source(pos)
             .map(|arc| arc.to_string())
             .unwrap_or_default()
@@ -162,28 +678,44 @@ impl Editor {
         range
     }
     pub fn get_value_range_as_csv(&self, range: CellRange) -> String {
-        slice_to_csv_string(self.state().table.slice(range))
+        let mut state = self.state();
+        state.evaluate();
+        slice_to_csv_string(state.table.slice(range))
     }
     pub fn set_source(&self, pos: CellPos, src: String) {
-        self.state().table.set_source(pos, Some(src));
+        let mut state = self.state();
+        state.set_source(pos, Some(Arc::from(src)));
+        state.commit_history();
+    }
+    pub fn undo(&self) {
+        let pos = self.state().undo();
+        match pos {
+            Some(pos) => self.render_changed(pos),
+            None => util::notify_err("Already at oldest change"),
+        }
+    }
+    pub fn redo(&self) {
+        let pos = self.state().redo();
+        match pos {
+            Some(pos) => self.render_changed(pos),
+            None => util::notify_err("Already at newest change"),
+        }
+    }
+    /// Renders the table after an undo or redo, moving the cursor to the changed cell if any
+    fn render_changed(&self, pos: Option<CellPos>) {
+        let pos = pos.unwrap_or_else(util::current_cell_pos);
+        self.render();
+        util::set_cursor_to_cell_pos(pos);
     }
     pub fn yank_source(&self, pos: CellPos) {
-        let mut editor = self.state();
-        let source = editor
-            .table
-            .get_source(pos)
-            .unwrap_or(&Arc::from(""))
-            .clone();
-
-        editor.clipboard.set(source);
+        self.yank_source_range(CellRange::from((pos, (pos.x + 1, pos.y + 1))));
     }
     pub fn yank_current_source(&self) {
         let pos = util::current_cell_pos();
         self.yank_source(pos);
     }
     pub fn yank_value(&self, pos: CellPos) {
-        self.state().table.evaluate();
-        let value = self.get_value(pos);
+        let value = self.get_evaluated_value(pos);
         let mut editor = self.state();
         editor.clipboard.set(value.into());
     }
@@ -199,6 +731,77 @@ impl Editor {
         let range = self.get_current_visual_range();
         self.yank_value_range_as_csv(range);
     }
+    /// Yanks the sources of the range as a block that is pasted back as a grid
+    pub fn yank_source_range(&self, range: CellRange) {
+        let mut state = self.state();
+        let sources = range
+            .rows()
+            .map(|row| {
+                range
+                    .columns()
+                    .map(|col| {
+                        let pos = (range.start.x + col, range.start.y + row);
+                        state.get_source(pos).cloned()
+                    })
+                    .collect()
+            })
+            .collect();
+        let block = Block::new(range.start, sources);
+        state.clipboard.set(block.text());
+        clipboard::set_block(block);
+    }
+    pub fn yank_current_source_range(&self) {
+        let range = self.get_current_visual_range();
+        self.yank_source_range(range);
+    }
+
+    /// The range from A0 to the bottom-right-most cell with a source
+    pub fn used_range(&self) -> CellRange {
+        let (width, height) = self
+            .state()
+            .sources
+            .keys()
+            .fold((0, 0), |(w, h), pos| (w.max(pos.x + 1), h.max(pos.y + 1)));
+        CellRange::from(((0, 0), (width, height)))
+    }
+    /// Sources or values of the cells in the range as rows of strings
+    pub fn range_records(&self, range: CellRange, sources: bool) -> Vec<Vec<String>> {
+        let mut state = self.state();
+        if sources {
+            return range
+                .rows()
+                .map(|row| {
+                    range
+                        .columns()
+                        .map(|col| {
+                            let pos = (range.start.x + col, range.start.y + row);
+                            state
+                                .get_source(pos)
+                                .map(|s| s.to_string())
+                                .unwrap_or_default()
+                        })
+                        .collect()
+                })
+                .collect();
+        }
+        state.evaluate();
+        state
+            .table
+            .slice(range)
+            .rows()
+            .map(|row| {
+                row.into_iter()
+                    .map(|v| v.map(|v| v.to_string()).unwrap_or_default())
+                    .collect()
+            })
+            .collect()
+    }
+    /// Formats the used area of the table as csv, writing either sources or values of the cells
+    pub fn to_csv(&self, options: &csv::CsvOptions) -> String {
+        let sources = options.write == csv::WriteMode::Sources;
+        let records = self.range_records(self.used_range(), sources);
+        csv::write(&records, options)
+    }
 
     pub fn start_editing_cell(&self, pos: CellPos) -> Buffer {
         let buffer = nvim::api::create_buf(true, false).unwrap();
@@ -207,14 +810,18 @@ impl Editor {
     }
 
     pub fn plot_segments(&self, range: CellRange, path: &Path) -> Result<(), anyhow::Error> {
+        let mut state = self.state();
+        state.evaluate();
         Ok(bight::plot::plot_segments_to_file(
-            self.state().table.slice(range),
+            state.table.slice(range),
             path,
         )?)
     }
     pub fn plot_auto(&self, range: CellRange, path: &Path) -> Result<(), anyhow::Error> {
+        let mut state = self.state();
+        state.evaluate();
         Ok(bight::plot::plot_auto_to_file(
-            self.state().table.slice(range),
+            state.table.slice(range),
             path,
         )?)
     }
@@ -223,8 +830,10 @@ impl Editor {
         range: CellRange,
         path: &Path,
     ) -> Result<Vec<(f64, f64)>, anyhow::Error> {
+        let mut state = self.state();
+        state.evaluate();
         Ok(bight::plot::plot_linear_to_file(
-            self.state().table.slice(range),
+            state.table.slice(range),
             path,
         )?)
     }
@@ -232,7 +841,6 @@ impl Editor {
     pub fn attach_cell_to_buffer(&self, pos: CellPos, mut buffer: Buffer) {
         let source = self
             .state()
-            .table
             .get_source(pos)
             .map(|s| s.lines().map(String::from).collect::<Vec<_>>())
             .unwrap_or_default();
@@ -274,10 +882,18 @@ fn prepare_cell_buffer(pos: CellPos, buffer: &mut Buffer) {
     .unwrap();
 }
 
-fn format_row(row: RowSlice<'_, EvaluatorTable>) -> impl Iterator<Item = char> {
-    row.into_iter().flat_map(|v| {
-        v.unwrap_or(&TableValue::Empty)
-            .format_to_length(CELL_WIDTH)
+fn format_row<'a>(
+    row: RowSlice<'a, ShownValues<'a>>,
+    widths: &'a ColumnWidths,
+    pending: &'a HashMap<CellPos, TableValue>,
+) -> impl Iterator<Item = char> + 'a {
+    let start = row.into_inner().start();
+    row.into_iter().zip(start.x..).flat_map(move |(v, x)| {
+        pending
+            .get(&CellPos::from((x, start.y)))
+            .or(v)
+            .unwrap_or(&TableValue::Empty)
+            .format_to_length(widths.get(x))
             .chars()
             .collect::<Vec<_>>()
             .into_iter()
@@ -288,9 +904,10 @@ pub fn render_buffer_edit(editor: &Editor, pos: CellPos, replace_input: bool) {
     let display_width = nvim::api::get_current_win().get_width().unwrap() as usize;
     let height = nvim::api::get_current_win().get_height().unwrap() as usize;
 
-    let width_cells = display_width.div_ceil(CELL_UNIT_WIDTH);
-
-    let (line, col) = cursor_position(pos);
+    let viewport = editor.viewport();
+    let origin = viewport.origin;
+    let width_cells = viewport.columns_to_fill(display_width);
+    let (line, col) = cursor_position(&viewport, pos);
 
     let replace_start_x = col;
     let replace_y = line - 1;
@@ -299,24 +916,26 @@ pub fn render_buffer_edit(editor: &Editor, pos: CellPos, replace_input: bool) {
     let line_to_edit = get_buffer_line(&editor.state().buffer, replace_y);
 
     let mut editor = editor.lock().unwrap();
-    editor.table.evaluate();
+    editor.update_values();
 
     let source = String::from(
         editor
-            .table
             .get_source(pos)
             .map_or("", |v| v.lines().next().unwrap_or("")),
     );
 
-    let slice = editor
-        .table
-        .slice((0, 0)..=(width_cells as isize, height as isize));
+    let range = CellRange::from(
+        (origin.x, origin.y)..=(origin.x + width_cells as isize, origin.y + height as isize),
+    );
+    let values = editor.shown_values();
+    let slice = values.slice(range);
+    let pending = editor.pending_values(range);
 
     let lines: Vec<_> = slice
         .rows()
         .map(|row| {
-            let line = format_row(row);
-            if row.into_inner().start().y != replace_y as isize {
+            let line = format_row(row, &viewport.widths, &pending);
+            if row.into_inner().start().y != pos.y {
                 line.take(display_width).collect::<String>()
             } else if replace_input {
                 line.take(replace_start_x)
@@ -338,7 +957,7 @@ pub fn render_buffer_edit(editor: &Editor, pos: CellPos, replace_input: bool) {
 
     drop(editor);
 
-    buffer.set_lines(0..height, false, lines).unwrap();
+    util::set_changed_lines(&mut buffer, lines);
 
     const REPLACE_MARK_ID: u32 = 2724982938; // some random number
 
@@ -356,9 +975,13 @@ pub fn render_buffer_edit(editor: &Editor, pos: CellPos, replace_input: bool) {
                 .build(),
         )
         .unwrap();
+
+    header::draw_headers(&viewport);
 }
 
 fn render_buffer(editor: &Editor) {
+    editor.evaluate_in_background();
+    let handle = editor.clone();
     let replace_pos = editor.lock().unwrap().edit;
     if let Some(pos) = replace_pos {
         render_buffer_edit(editor, pos, false);
@@ -368,22 +991,72 @@ fn render_buffer(editor: &Editor) {
     let height = nvim::api::get_current_win().get_height().unwrap() as usize;
 
     let mut editor = editor.lock().unwrap();
-    editor.table.evaluate();
+    editor.update_values();
+    let new_cycles = editor.update_cycles();
 
-    let width_cells = display_width.div_ceil(CELL_UNIT_WIDTH);
+    let origin = editor.viewport.origin;
+    let width_cells = editor.viewport.columns_to_fill(display_width);
 
-    let slice = editor
-        .table
-        .slice((0, 0)..=(width_cells as isize, height as isize));
+    let range = CellRange::from(
+        (origin.x, origin.y)..=(origin.x + width_cells as isize, origin.y + height as isize),
+    );
+    let values = editor.shown_values();
+    let slice = values.slice(range);
+    let pending = editor.pending_values(range);
 
     let lines: Vec<_> = slice
         .rows()
-        .map(|row| format_row(row).collect::<String>())
+        .map(|row| format_row(row, &editor.viewport.widths, &pending).collect::<String>())
         .collect();
 
     let mut buffer = editor.buffer.clone();
+    let viewport = editor.viewport.clone();
 
     drop(editor);
 
-    buffer.set_lines(0..height, false, lines).unwrap();
+    util::set_changed_lines(&mut buffer, lines);
+
+    header::draw_headers(&viewport);
+    dependency::draw_dependency_marks(&handle, &viewport);
+    diagnostic::draw_errors(&handle, &viewport);
+    for cycle in new_cycles {
+        util::notify_err(&format!("Circular reference: {}", format_cycle(&cycle)));
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn fill() {
+        assert_eq!(
+            fill_lane(&["=B0*2", "", "", ""], Axis::Row),
+            (1, vec!["=B1*2".into(), "=B2*2".into(), "=B3*2".into()])
+        );
+        assert_eq!(
+            fill_lane(&["=A1+1", "x", ""], Axis::Column),
+            (1, vec!["=B1+1".into(), "=C1+1".into()])
+        );
+        assert_eq!(
+            fill_lane(&["1", "3", "", ""], Axis::Row),
+            (2, vec!["5".into(), "7".into()])
+        );
+    }
+
+    #[test]
+    fn fill_over_populated_range() {
+        assert_eq!(
+            fill_lane(&["=B0", "old", "data", ""], Axis::Row),
+            (1, vec!["=B1".into(), "=B2".into(), "=B3".into()])
+        );
+        assert_eq!(
+            fill_lane(&["1", "2", "", "old"], Axis::Row),
+            (1, vec!["1".into(), "1".into(), "1".into()])
+        );
+        assert_eq!(
+            fill_lane(&["1", "2", "3"], Axis::Row),
+            (1, vec!["1".into(), "1".into()])
+        );
+    }
 }
@@ -19,8 +19,7 @@ impl UserData for Editor {
             Ok(())
         });
         methods.add_method("get_value", |_, this, pos: CellPos| {
-            this.state().table.evaluate();
-            Ok(this.get_value(pos))
+            Ok(this.get_evaluated_value(pos))
         });
         methods.add_method("get_source", |_, this, pos: CellPos| {
             Ok(this.get_source(pos))
@@ -1,4 +1,4 @@
-use std::{path::Path, str::FromStr, sync::Arc};
+use std::{path::Path, str::FromStr};
 
 use bight::{
     file::BightFile,
@@ -14,9 +14,17 @@ use nvim_oxi::{
 };
 
 use crate::{
-    editor::{Editor, add_keymaps, render_buffer, render_buffer_edit},
-    enotify, notify,
-    util::{current_cell_pos, get_buffer_as_string, normalize_cursor, notify_err},
+    csv,
+    editor::{
+        Editor, add_keymaps, draw_dependency_marks, draw_headers, render_buffer, render_buffer_edit,
+    },
+    enotify,
+    export::ExportFormat,
+    notify,
+    reference::Axis,
+    sort::SortKey,
+    substitute::Substitution,
+    util::{current_cell_pos, get_buffer_as_string, goto_address, normalize_cursor, notify_err},
 };
 
 pub fn init_buffer(mut buffer: Buffer, file: Option<&Path>) {
@@ -93,9 +101,15 @@ fn attach_buffer_autocmd(mut buffer: Buffer, editor: Editor) {
             &CreateAutocmdOpts::builder()
                 .callback(move |args: AutocmdCallbackArgs| {
                     let file = args.file;
-                    let source = editor.lock().unwrap().table.source_table().clone();
-                    let bfile = BightFile::new(source);
-                    if let Err(e) = bight::file::save(&file, &bfile) {
+                    let result = if csv::is_csv(&file) {
+                        let text = editor.to_csv(&csv::csv_options());
+                        std::fs::write(&file, text).map_err(anyhow::Error::from)
+                    } else {
+                        let source = editor.lock().unwrap().sources.clone();
+                        let bfile = BightFile::new(source);
+                        bight::file::save(&file, &bfile).map_err(anyhow::Error::from)
+                    };
+                    if let Err(e) = result {
                         notify_err(&format!("Failed to save file {file:?}: {e}"));
                         return false;
                     } else {
@@ -189,10 +203,287 @@ fn attach_buffer_autocmd(mut buffer: Buffer, editor: Editor) {
             .unwrap();
     }
 
+    {
+        let editor = editor.clone();
+        buffer
+            .create_user_command(
+                "BightExport",
+                move |args: CommandArgs| {
+                    let Some((path, other)) = args.fargs.split_first() else {
+                        enotify!("Not enough arguments to export! Required: path");
+                        return;
+                    };
+                    let path = Path::new(path);
+                    let mut range = None;
+                    let mut format = ExportFormat::from_path(path);
+                    let mut sources = false;
+                    for arg in other {
+                        match arg.to_lowercase().as_str() {
+                            "values" => sources = false,
+                            "sources" => sources = true,
+                            lower => {
+                                if let Ok(f) = lower.parse::<ExportFormat>() {
+                                    format = Some(f);
+                                } else if let Ok(r) = CellRange::from_str(arg) {
+                                    range = Some(r);
+                                } else {
+                                    enotify!("Invalid export argument {arg}!");
+                                    return;
+                                }
+                            }
+                        }
+                    }
+                    let Some(format) = format else {
+                        enotify!("Unknown export format! Accepted: csv, tsv, markdown (md), html.");
+                        return;
+                    };
+                    let range = range.unwrap_or_else(|| editor.used_range());
+                    let text = format.format(&editor.range_records(range, sources));
+                    match std::fs::write(path, text) {
+                        Ok(()) => notify!("Exported to {path:?}"),
+                        Err(e) => enotify!("Failed to export to {path:?}: {e}"),
+                    }
+                },
+                &CreateCommandOpts::builder()
+                    .nargs(nvim_oxi::api::types::CommandNArgs::OneOrMore)
+                    .complete(nvim_oxi::api::types::CommandComplete::File)
+                    .build(),
+            )
+            .unwrap();
+    }
+
+    buffer
+        .create_user_command(
+            "BightGoto",
+            |args: CommandArgs| goto_address(&args.args.unwrap_or_default()),
+            &CreateCommandOpts::builder()
+                .nargs(nvim_oxi::api::types::CommandNArgs::One)
+                .build(),
+        )
+        .unwrap();
+
+    {
+        let editor = editor.clone();
+        buffer
+            .create_user_command(
+                "BightDependents",
+                move |_args: CommandArgs| editor.list_dependents(),
+                &CreateCommandOpts::builder().build(),
+            )
+            .unwrap();
+    }
+
+    {
+        let editor = editor.clone();
+        buffer
+            .create_user_command(
+                "BightErrors",
+                move |_args: CommandArgs| editor.list_errors(),
+                &CreateCommandOpts::builder().build(),
+            )
+            .unwrap();
+    }
+
+    {
+        let editor = editor.clone();
+        buffer
+            .create_user_command(
+                "BightCancelEvaluation",
+                move |_args: CommandArgs| editor.cancel_evaluation(),
+                &CreateCommandOpts::builder().build(),
+            )
+            .unwrap();
+    }
+
+    {
+        let editor = editor.clone();
+        buffer
+            .create_user_command(
+                "BightFind",
+                move |args: CommandArgs| {
+                    let pattern = args.args.unwrap_or_default();
+                    let matches = editor.find(&pattern);
+                    editor.set_quickfix(&format!("BightFind {pattern}"), &matches);
+                    let count = matches.len();
+                    editor.set_matches(matches);
+                    if count == 0 {
+                        enotify!("Pattern not found: {pattern}");
+                    } else {
+                        editor.jump_to_match(true, 1);
+                        notify!("{count} matches of {pattern}");
+                    }
+                },
+                &CreateCommandOpts::builder()
+                    .nargs(nvim_oxi::api::types::CommandNArgs::OneOrMore)
+                    .build(),
+            )
+            .unwrap();
+    }
+
+    {
+        let editor = editor.clone();
+        buffer
+            .create_user_command(
+                "BightReplace",
+                move |args: CommandArgs| {
+                    let args = args.args.unwrap_or_default();
+                    let (substitution, range) = match Substitution::parse(&args) {
+                        Ok(v) => v,
+                        Err(e) => {
+                            enotify!("Invalid substitution: {e}");
+                            return;
+                        }
+                    };
+                    let range = if range.is_empty() {
+                        None
+                    } else {
+                        match CellRange::from_str(range) {
+                            Ok(r) => Some(r),
+                            Err(_) => {
+                                enotify!("Invalid range {range} was passed!");
+                                return;
+                            }
+                        }
+                    };
+                    match editor.replace(&substitution, range) {
+                        Ok(count) if substitution.count_only => {
+                            notify!("{count} cells would be changed")
+                        }
+                        Ok(count) => notify!("Changed {count} cells"),
+                        Err(e) => enotify!("Failed to replace: {e}"),
+                    }
+                },
+                &CreateCommandOpts::builder()
+                    .nargs(nvim_oxi::api::types::CommandNArgs::OneOrMore)
+                    .build(),
+            )
+            .unwrap();
+    }
+
+    {
+        let editor = editor.clone();
+        buffer
+            .create_user_command(
+                "BightSort",
+                move |args: CommandArgs| {
+                    let Some((range, keys)) = args.fargs.split_first() else {
+                        enotify!("Not enough arguments to sort! Required: range");
+                        return;
+                    };
+                    let range = match CellRange::from_str(range) {
+                        Ok(r) => r,
+                        Err(_) => {
+                            enotify!("Invalid range {range} was passed!");
+                            return;
+                        }
+                    };
+                    let mut sort_keys = Vec::new();
+                    for key in keys {
+                        match SortKey::parse(key) {
+                            Some(k) if range.columns().contains(&(k.x - range.start.x)) => {
+                                sort_keys.push(k)
+                            }
+                            Some(_) => {
+                                enotify!("Sort column {key} is outside of the range!");
+                                return;
+                            }
+                            None => {
+                                enotify!("Invalid sort column {key}! Expected e.g. B or B!");
+                                return;
+                            }
+                        }
+                    }
+                    if sort_keys.is_empty() {
+                        sort_keys.push(SortKey {
+                            x: range.start.x,
+                            descending: false,
+                        });
+                    }
+                    editor.sort(range, &sort_keys);
+                },
+                &CreateCommandOpts::builder()
+                    .nargs(nvim_oxi::api::types::CommandNArgs::OneOrMore)
+                    .build(),
+            )
+            .unwrap();
+    }
+
+    {
+        let editor = editor.clone();
+        buffer
+            .create_user_command(
+                "BightColumnWidth",
+                move |args: CommandArgs| {
+                    let arg = args.fargs.first().map_or("auto", |v| v).to_lowercase();
+                    let x = current_cell_pos().x;
+                    if arg == "auto" {
+                        editor.fit_column(x);
+                        return;
+                    }
+                    let width = if let Some(delta) = arg.strip_prefix('+') {
+                        delta
+                            .parse::<usize>()
+                            .map(|delta| editor.column_width(x).saturating_add(delta))
+                    } else if let Some(delta) = arg.strip_prefix('-') {
+                        delta
+                            .parse::<usize>()
+                            .map(|delta| editor.column_width(x).saturating_sub(delta))
+                    } else {
+                        arg.parse::<usize>()
+                    };
+                    match width {
+                        Ok(width) => editor.set_column_width(x, width),
+                        Err(_) => {
+                            enotify!("Invalid column width {arg}! Accepted: N, +N, -N, auto.");
+                        }
+                    }
+                },
+                &CreateCommandOpts::builder()
+                    .nargs(nvim_oxi::api::types::CommandNArgs::ZeroOrOne)
+                    .build(),
+            )
+            .unwrap();
+    }
+
+    for (name, axis, insert) in [
+        ("BightInsertRow", Axis::Row, true),
+        ("BightInsertColumn", Axis::Column, true),
+        ("BightDeleteRow", Axis::Row, false),
+        ("BightDeleteColumn", Axis::Column, false),
+    ] {
+        let editor = editor.clone();
+        buffer
+            .create_user_command(
+                name,
+                move |args: CommandArgs| {
+                    let arg = args.fargs.first().map_or("1", |v| v);
+                    let Ok(count) = arg.parse::<isize>() else {
+                        enotify!("Invalid count {arg}!");
+                        return;
+                    };
+                    let pos = current_cell_pos();
+                    let at = match axis {
+                        Axis::Row => pos.y,
+                        Axis::Column => pos.x,
+                    };
+                    if insert {
+                        editor.insert(axis, at, count);
+                    } else {
+                        editor.delete(axis, at, count);
+                    }
+                },
+                &CreateCommandOpts::builder()
+                    .nargs(nvim_oxi::api::types::CommandNArgs::ZeroOrOne)
+                    .build(),
+            )
+            .unwrap();
+    }
+
     let cb_to_v = {
         let editor = editor.clone();
         move || {
-            editor.lock().unwrap().visual_start = current_cell_pos();
+            let pos = current_cell_pos();
+            editor.lock().unwrap().visual_start = pos;
         }
     };
 
@@ -213,6 +504,38 @@ fn attach_buffer_autocmd(mut buffer: Buffer, editor: Editor) {
     )
     .unwrap();
 
+    {
+        let editor = editor.clone();
+        nvim::api::create_autocmd(
+            ["CursorMoved"],
+            &CreateAutocmdOpts::builder()
+                .buffer(buffer.clone())
+                .callback(move |_args: AutocmdCallbackArgs| {
+                    let viewport = editor.viewport();
+                    draw_headers(&viewport);
+                    draw_dependency_marks(&editor, &viewport);
+                    false
+                })
+                .build(),
+        )
+        .unwrap();
+    }
+
+    {
+        let editor = editor.clone();
+        nvim::api::create_autocmd(
+            ["CursorMoved", "BufEnter"],
+            &CreateAutocmdOpts::builder()
+                .buffer(buffer.clone())
+                .callback(move |_args: AutocmdCallbackArgs| {
+                    editor.follow_cell_list();
+                    false
+                })
+                .build(),
+        )
+        .unwrap();
+    }
+
     nvim::api::create_autocmd(
         ["InsertLeave"],
         &CreateAutocmdOpts::builder()
@@ -240,10 +563,7 @@ pub fn attach_cell_edit_autocmd(pos: CellPos, buffer: Buffer, editor: Editor) {
                     &OptionOpts::builder().buf(buffer.clone()).build(),
                 )
                 .unwrap();
-                editor
-                    .state()
-                    .table
-                    .set_source(pos, Some(Arc::from(content)));
+                editor.set_source(pos, content);
                 let editor_buf = editor.lock().unwrap().buffer.clone();
                 render_buffer(&editor);
                 nvim::api::set_option_value(
@@ -0,0 +1,378 @@
+use std::{
+    error::Error,
+    fmt::Display,
+    iter,
+    sync::{
+        Arc,
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        mpsc::{self, Receiver, Sender, TryRecvError},
+    },
+    thread,
+};
+
+use bight::{
+    evaluator::{EvaluatorTable, SourceTable, TableError, TableValue, ValueTable},
+    table::{CellRange, Table, cell::CellPos},
+};
+use hashbrown::{HashMap, HashSet};
+use nvim_oxi::{self as nvim, Dictionary};
+
+use crate::{
+    editor::{Editor, EditorState},
+    reference::references,
+    util::{self, get_as_bool_or, get_as_integer, nvim_mlua},
+};
+
+static ASYNC_EVALUATION: AtomicBool = AtomicBool::new(false);
+/// Steps a formula may take, 0 if there is no limit
+static EVALUATION_LIMIT: AtomicU64 = AtomicU64::new(DEFAULT_EVALUATION_LIMIT);
+const DEFAULT_EVALUATION_LIMIT: u64 = 10_000_000;
+
+/// Shown instead of the values of cells that are still being evaluated in the background
+pub const PENDING_PLACEHOLDER: &str = "…";
+
+/// Applies the `async_evaluation` (disabled by default) and `evaluation_limit` (ten million steps
+/// by default) setup options
+pub fn configure_evaluation(opts: &Dictionary) {
+    ASYNC_EVALUATION.store(
+        get_as_bool_or(opts, "async_evaluation", false),
+        Ordering::Relaxed,
+    );
+    let limit = match opts.get("evaluation_limit") {
+        None => DEFAULT_EVALUATION_LIMIT,
+        Some(_) if !get_as_bool_or(opts, "evaluation_limit", true) => 0,
+        Some(_) => match get_as_integer(opts, "evaluation_limit") {
+            Some(limit) if limit >= 0 => limit as u64,
+            _ => {
+                util::notify_err("evaluation_limit must be a number of steps or false");
+                DEFAULT_EVALUATION_LIMIT
+            }
+        },
+    };
+    EVALUATION_LIMIT.store(limit, Ordering::Relaxed);
+}
+
+/// Whether tables are evaluated on a background thread instead of while rendering
+pub fn is_async() -> bool {
+    ASYNC_EVALUATION.load(Ordering::Relaxed)
+}
+
+/// How many steps a formula may take before it fails with an "evaluation timed out" error, see
+/// `budget::limit_steps`
+pub fn evaluation_limit() -> Option<u64> {
+    match EVALUATION_LIMIT.load(Ordering::Relaxed) {
+        0 => None,
+        limit => Some(limit),
+    }
+}
+
+/// Error value of the cells whose background evaluation was given up
+#[derive(Debug)]
+pub struct Interrupted(pub &'static str);
+
+impl Display for Interrupted {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.0)
+    }
+}
+
+impl Error for Interrupted {}
+
+/// The values shown in the buffer, see `EditorState::shown_values`
+pub enum ShownValues<'a> {
+    Evaluated(&'a EvaluatorTable),
+    Background(&'a ValueTable),
+}
+
+impl Table for ShownValues<'_> {
+    type Item = TableValue;
+    fn get(&self, pos: CellPos) -> Option<&TableValue> {
+        match self {
+            Self::Evaluated(table) => table.get(pos),
+            Self::Background(values) => values.get(&pos),
+        }
+    }
+}
+
+/// Changed evaluation sources sent to the worker with the `EditorState::generation` they belong to
+type Update = (u64, Vec<(CellPos, Option<Arc<str>>)>);
+
+/// Values evaluated by the worker with the sources they were evaluated from
+pub struct Evaluated {
+    generation: u64,
+    sources: SourceTable,
+    values: ValueTable,
+}
+
+/// A thread keeping its own copy of the table and evaluating it whenever changed sources are sent
+/// to it. Only the changed cells and their dependents are evaluated again, and changes sent while
+/// an evaluation runs are evaluated together once it finishes.
+pub struct Worker {
+    /// Changes of the evaluation sources not sent yet
+    changes: HashMap<CellPos, Option<Arc<str>>>,
+    /// `EditorState::generation` of the last sent sources
+    sent: u64,
+    sender: Sender<Update>,
+    receiver: Receiver<Evaluated>,
+}
+
+impl Worker {
+    /// Starts the thread. All of `sources` are sent to it with the first `send`.
+    fn spawn(sources: &SourceTable) -> Self {
+        let (sender, updates) = mpsc::channel::<Update>();
+        let (results, receiver) = mpsc::channel();
+        thread::spawn(move || {
+            let mut table = EvaluatorTable::default();
+            while let Ok(update) = updates.recv() {
+                let mut generation = 0;
+                for (sent, changes) in iter::once(update).chain(updates.try_iter()) {
+                    generation = sent;
+                    for (pos, source) in changes {
+                        table.set_source(pos, source);
+                    }
+                }
+                table.evaluate();
+                let sources = table.source_table().clone();
+                let values = sources
+                    .keys()
+                    .filter_map(|&pos| Some((pos, table.get(pos)?.clone())))
+                    .collect();
+                let evaluated = Evaluated {
+                    generation,
+                    sources,
+                    values,
+                };
+                // Fails once the editor is dropped, the thread is not needed anymore then
+                if results.send(evaluated).is_err() {
+                    break;
+                }
+            }
+        });
+        Self {
+            changes: sources
+                .iter()
+                .map(|(&pos, source)| (pos, Some(source.clone())))
+                .collect(),
+            sent: 0,
+            sender,
+            receiver,
+        }
+    }
+    /// Records the change of an evaluation source, it is sent with the next `send`
+    pub fn record(&mut self, pos: CellPos, source: Option<Arc<str>>) {
+        self.changes.insert(pos, source);
+    }
+    fn send(&mut self, generation: u64) {
+        let changes = self.changes.drain().collect();
+        // Fails if the thread panicked, which `poll` reports
+        let _ = self.sender.send((generation, changes));
+        self.sent = generation;
+    }
+    /// The latest values evaluated since the last call, or an error if the thread panicked
+    fn poll(&self) -> Result<Option<Evaluated>, TryRecvError> {
+        let mut latest = None;
+        loop {
+            match self.receiver.try_recv() {
+                Ok(evaluated) => latest = Some(evaluated),
+                Err(TryRecvError::Empty) => return Ok(latest),
+                Err(error) => return Err(error),
+            }
+        }
+    }
+}
+
+impl EditorState {
+    /// Evaluates the table if values are read from it, which is the case unless evaluation is
+    /// asynchronous
+    pub fn update_values(&mut self) {
+        if !is_async() {
+            self.evaluate();
+        }
+    }
+    /// The values to show: the ones of the table itself, or the ones last evaluated in the
+    /// background if evaluation is asynchronous. Call `update_values` first.
+    pub fn shown_values(&self) -> ShownValues<'_> {
+        if is_async() {
+            ShownValues::Background(&self.values)
+        } else {
+            ShownValues::Evaluated(&self.table)
+        }
+    }
+    /// Values to show instead of the ones of `shown_values` for the cells in `range`: an error
+    /// for the cancelled cells, and a placeholder for the cells whose sources, or the sources of
+    /// the cells they depend on, changed after the shown values were evaluated
+    pub fn pending_values(&self, range: CellRange) -> HashMap<CellPos, TableValue> {
+        if !is_async() {
+            return HashMap::new();
+        }
+        let mut pending = HashMap::new();
+        if self.values_generation != self.generation {
+            let placeholder = TableValue::Text(PENDING_PLACEHOLDER.into());
+            for pos in self.unevaluated_cells() {
+                if range.is_inside(pos) {
+                    pending.insert(pos, placeholder.clone());
+                }
+            }
+        }
+        for (&pos, &reason) in &self.cancelled {
+            if range.is_inside(pos) {
+                let error = TableError::OtherError(Arc::new(Interrupted(reason)));
+                pending.insert(pos, TableValue::Err(error));
+            }
+        }
+        pending
+    }
+    /// Cells whose sources in the worker differ from the ones the shown values were evaluated
+    /// from, and the cells referencing them directly or indirectly
+    fn unevaluated_cells(&self) -> HashSet<CellPos> {
+        let sources = self.worker_sources();
+        let mut stack: Vec<CellPos> = sources
+            .iter()
+            .filter(|(pos, source)| self.values_sources.get(*pos) != Some(*source))
+            .map(|(&pos, _)| pos)
+            .chain(
+                self.values_sources
+                    .keys()
+                    .filter(|pos| !sources.contains_key(*pos))
+                    .copied(),
+            )
+            .collect();
+        let mut dependents: HashMap<CellPos, Vec<CellPos>> = HashMap::new();
+        for (&pos, source) in &self.sources {
+            if source.starts_with('=') {
+                for reference in references(source) {
+                    dependents.entry(reference).or_default().push(pos);
+                }
+            }
+        }
+        let mut unevaluated = HashSet::new();
+        while let Some(pos) = stack.pop() {
+            if unevaluated.insert(pos) {
+                stack.extend(dependents.get(&pos).into_iter().flatten());
+            }
+        }
+        unevaluated
+    }
+    /// The evaluation sources with the ones of the cancelled cells replaced by a formula failing
+    /// with the reason, which are the sources the worker evaluates
+    fn worker_sources(&self) -> SourceTable {
+        let mut sources = self.table.source_table().clone();
+        for (&pos, &reason) in &self.cancelled {
+            sources.insert(pos, Arc::from(format!("=error({reason:?}, 0)")));
+        }
+        sources
+    }
+    /// Sends the changed sources to the worker unless they are already evaluated or being
+    /// evaluated. Returns false if nothing was sent.
+    fn start_evaluation(&mut self) -> bool {
+        let generation = self.generation;
+        if !is_async() || self.values_generation == generation {
+            return false;
+        }
+        if self.worker.is_none() {
+            self.worker = Some(Worker::spawn(&self.worker_sources()));
+        }
+        let worker = self.worker.as_mut().unwrap();
+        if worker.sent == generation {
+            return false;
+        }
+        worker.send(generation);
+        true
+    }
+    /// Whether the worker is evaluating sources whose values are still waited for
+    fn is_evaluating(&self) -> bool {
+        self.worker
+            .as_ref()
+            .is_some_and(|worker| worker.sent > self.values_generation)
+    }
+    /// Gives up the running background evaluation, showing `reason` as the value of the cells
+    /// still being evaluated. The worker can't be stopped in the middle of an evaluation, so it is
+    /// left to finish on its own, and a new one evaluates the sources without those cells until
+    /// they are changed again. Returns false if no evaluation was running.
+    pub fn interrupt_evaluation(&mut self, reason: &'static str) -> bool {
+        if !self.is_evaluating() {
+            return false;
+        }
+        for pos in self.unevaluated_cells() {
+            if self.table.get_source(pos).is_some() {
+                self.cancelled.insert(pos, reason);
+            }
+        }
+        self.worker = None;
+        self.start_evaluation();
+        true
+    }
+    fn show_evaluated(&mut self, evaluated: Evaluated) {
+        self.values_generation = evaluated.generation;
+        self.values_sources = evaluated.sources;
+        self.values = evaluated.values;
+    }
+}
+
+impl Editor {
+    /// Starts evaluating the table in the background if evaluation is asynchronous and the
+    /// sources changed. The buffer is rendered again once the values are ready.
+    pub fn evaluate_in_background(&self) {
+        let poll = {
+            let mut state = self.state();
+            if !state.start_evaluation() || state.polling {
+                return;
+            }
+            state.polling = true;
+            let editor = self.clone();
+            nvim_mlua()
+                .create_function(move |_, ()| Ok(editor.poll_evaluation()))
+                .unwrap()
+        };
+        nvim_mlua()
+            .load(
+                r#"
+                local poll = ...
+                local timer = vim.uv.new_timer()
+                timer:start(10, 10, vim.schedule_wrap(function()
+                    if timer:is_active() and poll() then
+                        timer:stop()
+                        timer:close()
+                    end
+                end))
+                "#,
+            )
+            .call::<()>(poll)
+            .unwrap();
+    }
+    /// Takes the values evaluated by the worker and renders them. Returns true when there is
+    /// nothing left to wait for.
+    fn poll_evaluation(&self) -> bool {
+        let (updated, waiting) = {
+            let mut state = self.state();
+            let Some(result) = state.worker.as_ref().map(Worker::poll) else {
+                state.polling = false;
+                return true;
+            };
+            let mut updated = true;
+            match result {
+                Ok(Some(evaluated)) => state.show_evaluated(evaluated),
+                Ok(None) => updated = false,
+                Err(_) => {
+                    state.interrupt_evaluation("evaluation failed");
+                    util::notify_err("Background evaluation failed");
+                }
+            }
+            let waiting = state.is_evaluating();
+            state.polling = waiting;
+            (updated, waiting)
+        };
+        if updated && nvim::api::get_current_buf().handle() == self.state().buffer.handle() {
+            self.render();
+        }
+        !waiting
+    }
+    /// Gives up the running background evaluation, see `EditorState::interrupt_evaluation`
+    pub fn cancel_evaluation(&self) {
+        if self.state().interrupt_evaluation("evaluation cancelled") {
+            self.render();
+        } else {
+            util::notify("No evaluation is running");
+        }
+    }
+}
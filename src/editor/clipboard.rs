@@ -1,14 +1,89 @@
-// use bight::clipboard::ClipboardProvider;
-// use nvim_oxi as nvim;
-//
-// pub struct NvimClipboard;
-//
-// impl ClipboardProvider for NvimClipboard {
-//     fn get_str(&mut self) -> Option<String> {
-//         nvim::api::get_var("register").unwrap();
-//         None
-//     }
-//     fn set_str(&mut self, v: &str) {
-//
-//     }
-// }
+use std::sync::{Arc, Mutex};
+
+use bight::{clipboard::ClipboardProvider, table::cell::CellPos};
+
+use crate::{
+    csv::{self, CsvOptions},
+    util::vim_fn,
+};
+
+/// Clipboard backed by Neovim registers. Uses the register of the current command (`v:register`),
+/// so `"a` prefixes and `clipboard=unnamedplus` are honored.
+pub struct NvimClipboard;
+
+fn current_register() -> String {
+    nvim_oxi::api::get_vvar::<String>("register").unwrap_or_else(|_| String::from("\""))
+}
+
+impl ClipboardProvider for NvimClipboard {
+    fn get_str(&mut self) -> Option<String> {
+        vim_fn("getreg").call::<String>(current_register()).ok()
+    }
+    fn set_str(&mut self, v: &str) {
+        let register = current_register();
+        let setreg = vim_fn("setreg");
+        setreg.call::<()>((register.as_str(), v)).unwrap();
+        // Like yanks in vim, also store the value in the yank register if no register was given
+        if matches!(register.as_str(), "\"" | "+" | "*") {
+            setreg.call::<()>(("0", v)).unwrap();
+        }
+    }
+}
+
+/// Sources of a rectangular range of cells yanked as a whole
+#[derive(Debug, Clone)]
+pub struct Block {
+    /// Position of the top-left cell the block was yanked from
+    pub origin: CellPos,
+    /// Sources of the cells, row by row
+    pub sources: Vec<Vec<Option<Arc<str>>>>,
+    /// Text put into the register for the block, csv of the sources
+    text: Arc<str>,
+}
+
+impl Block {
+    pub fn new(origin: CellPos, sources: Vec<Vec<Option<Arc<str>>>>) -> Self {
+        let records: Vec<Vec<String>> = sources
+            .iter()
+            .map(|row| {
+                row.iter()
+                    .map(|source| source.as_deref().unwrap_or("").to_string())
+                    .collect()
+            })
+            .collect();
+        // A single cell is put into the register as is, so that it can be pasted into other buffers
+        let text = match records.as_slice() {
+            [row] if row.len() == 1 => row[0].as_str().into(),
+            _ => csv::write(&records, &CsvOptions::default()).into(),
+        };
+        Self {
+            origin,
+            sources,
+            text,
+        }
+    }
+    pub fn is_single_cell(&self) -> bool {
+        self.sources.len() == 1 && self.sources[0].len() == 1
+    }
+    pub fn text(&self) -> Arc<str> {
+        self.text.clone()
+    }
+}
+
+/// The last yanked block. It is shared between sheets so that blocks can be pasted into other
+/// bight buffers.
+static BLOCK: Mutex<Option<Block>> = Mutex::new(None);
+
+pub fn set_block(block: Block) {
+    *BLOCK.lock().unwrap() = Some(block);
+}
+
+/// The last yanked block if `text` (the contents of a register) is still the text of the block
+pub fn yanked_block(text: &str) -> Option<Block> {
+    BLOCK
+        .lock()
+        .unwrap()
+        .as_ref()
+        .filter(|block| block.text.trim_end_matches('\n') == text.trim_end_matches('\n'))
+        .cloned()
+}
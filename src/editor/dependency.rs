@@ -0,0 +1,143 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use bight::table::cell::CellPos;
+use nvim_oxi::{self as nvim, Dictionary, api::opts::SetExtmarkOpts};
+
+use crate::{
+    editor::{Editor, Viewport},
+    reference::references,
+    util::{self, cursor_position, get_as_bool_or, nvim_mlua, window_size},
+};
+
+static PRECEDENT_MARKS: AtomicBool = AtomicBool::new(true);
+static DEPENDENT_MARKS: AtomicBool = AtomicBool::new(false);
+
+/// Applies the `highlight_precedents` (enabled by default) and `highlight_dependents` (disabled
+/// by default) setup options
+pub fn configure_dependency_marks(opts: &Dictionary) {
+    PRECEDENT_MARKS.store(
+        get_as_bool_or(opts, "highlight_precedents", true),
+        Ordering::Relaxed,
+    );
+    DEPENDENT_MARKS.store(
+        get_as_bool_or(opts, "highlight_dependents", false),
+        Ordering::Relaxed,
+    );
+}
+
+/// Highlights the visible cells referenced by the current cell's formula with `BightPrecedent`
+/// and the cells referencing the current cell with `BightDependent`
+pub fn draw_dependency_marks(editor: &Editor, viewport: &Viewport) {
+    let namespace = nvim::api::create_namespace("BightDependencyMark");
+    let (mut buffer, editing) = {
+        let state = editor.state();
+        (state.buffer.clone(), state.edit.is_some())
+    };
+    buffer.clear_namespace(namespace, 0..).unwrap();
+    if editing {
+        return;
+    }
+
+    let pos = util::current_cell_pos();
+    let mut marks = Vec::new();
+    if PRECEDENT_MARKS.load(Ordering::Relaxed) {
+        marks.extend(
+            editor
+                .precedents(pos)
+                .into_iter()
+                .map(|p| (p, "BightPrecedent")),
+        );
+    }
+    if DEPENDENT_MARKS.load(Ordering::Relaxed) {
+        marks.extend(
+            editor
+                .dependents(pos)
+                .into_iter()
+                .map(|p| (p, "BightDependent")),
+        );
+    }
+
+    let (display_width, height) = window_size();
+    let origin = viewport.origin;
+    for (cell, group) in marks {
+        if cell.x < origin.x || cell.y < origin.y || cell.y >= origin.y + height as isize {
+            continue;
+        }
+        let (line, col) = cursor_position(viewport, cell);
+        if col >= display_width {
+            continue;
+        }
+        buffer
+            .set_extmark(
+                namespace,
+                line - 1,
+                col,
+                &SetExtmarkOpts::builder()
+                    .end_col(col + viewport.widths.get(cell.x))
+                    .hl_group(group)
+                    .strict(false)
+                    .build(),
+            )
+            .unwrap();
+    }
+}
+
+impl Editor {
+    /// Cells referenced in the formula of the cell at `pos`
+    pub fn precedents(&self, pos: CellPos) -> Vec<CellPos> {
+        self.state()
+            .get_source(pos)
+            .map(|source| references(source))
+            .unwrap_or_default()
+    }
+    /// Cells whose formulas reference the cell at `pos`, ordered by rows
+    pub fn dependents(&self, pos: CellPos) -> Vec<CellPos> {
+        let mut dependents: Vec<CellPos> = self
+            .state()
+            .sources
+            .iter()
+            .filter(|(_, source)| references(source).contains(&pos))
+            .map(|(&pos, _)| pos)
+            .collect();
+        dependents.sort_by_key(|pos| (pos.y, pos.x));
+        dependents
+    }
+    /// Moves the cursor to the cell referenced by the current cell's formula. If there are several
+    /// references, one is picked with `vim.ui.select`.
+    pub fn goto_precedent(&self) {
+        let precedents = self.precedents(util::current_cell_pos());
+        match precedents.as_slice() {
+            [] => util::notify_err("The cell does not reference other cells"),
+            [pos] => util::set_cursor_to_cell_pos(*pos),
+            _ => {
+                let lua = nvim_mlua();
+                let addresses: Vec<String> = precedents.iter().map(|pos| pos.to_string()).collect();
+                let opts = lua.create_table().unwrap();
+                opts.set("prompt", "Go to cell").unwrap();
+                let on_choice = lua
+                    .create_function(|_, address: Option<String>| {
+                        if let Some(address) = address {
+                            util::goto_address(&address);
+                        }
+                        Ok(())
+                    })
+                    .unwrap();
+                lua.load("vim.ui.select")
+                    .eval::<nvim_oxi::mlua::Function>()
+                    .and_then(|select| select.call::<()>((addresses, opts, on_choice)))
+                    .unwrap();
+            }
+        }
+    }
+    /// Puts the cells depending on the current cell into the location list
+    pub fn list_dependents(&self) {
+        let pos = util::current_cell_pos();
+        let dependents = self.dependents(pos);
+        if dependents.is_empty() {
+            util::notify(&format!("No cells depend on {pos}"));
+            return;
+        }
+        self.set_loclist(&format!("Dependents of {pos}"), &dependents);
+        util::notify(&format!("{} cells depend on {pos}", dependents.len()));
+    }
+}
@@ -0,0 +1,109 @@
+use bight::{
+    evaluator::TableValue,
+    table::{Table, cell::CellPos},
+};
+use nvim_oxi::{self as nvim, api::opts::SetExtmarkOpts};
+
+use crate::{
+    editor::{Editor, Viewport},
+    reference::format_cycle,
+    util::{self, cursor_position, nvim_mlua, window_size},
+};
+
+/// Highlights the visible cells whose evaluation failed or that are part of a reference cycle
+/// with `BightCellError` and publishes them through `vim.diagnostic`, one diagnostic with the
+/// full error message per cell. Only cells in the viewport have buffer positions, so the cells
+/// outside of it are counted in a notification pointing at `:BightErrors` instead, whenever their
+/// number changes.
+pub fn draw_errors(editor: &Editor, viewport: &Viewport) {
+    let namespace = nvim::api::create_namespace("BightCellError");
+    let (display_width, height) = window_size();
+    let origin = viewport.origin;
+
+    let (visible, hidden): (Vec<_>, Vec<_>) = editor.errors().into_iter().partition(|(pos, _)| {
+        pos.x >= origin.x
+            && pos.y >= origin.y
+            && pos.y < origin.y + height as isize
+            && cursor_position(viewport, *pos).1 < display_width
+    });
+    let (mut buffer, hidden_changed) = {
+        let mut state = editor.state();
+        let changed = state.hidden_errors != hidden.len();
+        state.hidden_errors = hidden.len();
+        (state.buffer.clone(), changed)
+    };
+    if hidden_changed && !hidden.is_empty() {
+        util::notify(&format!(
+            "{} cells outside of the view have errors, list them with :BightErrors",
+            hidden.len()
+        ));
+    }
+    buffer.clear_namespace(namespace, 0..).unwrap();
+
+    let lua = nvim_mlua();
+    let diagnostics = lua.create_table().unwrap();
+    for (pos, message) in visible {
+        let (line, col) = cursor_position(viewport, pos);
+        let end_col = col + viewport.widths.get(pos.x);
+        buffer
+            .set_extmark(
+                namespace,
+                line - 1,
+                col,
+                &SetExtmarkOpts::builder()
+                    .end_col(end_col)
+                    .hl_group("BightCellError")
+                    .strict(false)
+                    .build(),
+            )
+            .unwrap();
+
+        let diagnostic = lua.create_table().unwrap();
+        diagnostic.set("lnum", line - 1).unwrap();
+        diagnostic.set("col", col).unwrap();
+        diagnostic.set("end_col", end_col).unwrap();
+        diagnostic.set("severity", 1).unwrap(); // vim.diagnostic.severity.ERROR
+        diagnostic.set("source", "bight").unwrap();
+        diagnostic
+            .set("message", format!("{pos}: {message}"))
+            .unwrap();
+        diagnostics.push(diagnostic).unwrap();
+    }
+    lua.load("vim.diagnostic.set")
+        .eval::<nvim::mlua::Function>()
+        .and_then(|set| set.call::<()>((namespace, buffer.handle(), diagnostics)))
+        .unwrap();
+}
+
+impl Editor {
+    /// Cells whose evaluation failed or that are part of a reference cycle with their error
+    /// messages, ordered by rows
+    pub fn errors(&self) -> Vec<(CellPos, String)> {
+        let mut state = self.state();
+        state.update_values();
+        let mut errors: Vec<_> = state
+            .sources
+            .keys()
+            .filter_map(
+                |&pos| match (state.cycle_of(pos), state.shown_values().get(pos)) {
+                    (Some(cycle), _) => {
+                        Some((pos, format!("Circular reference: {}", format_cycle(cycle))))
+                    }
+                    (None, Some(value @ TableValue::Err(_))) => Some((pos, value.to_string())),
+                    _ => None,
+                },
+            )
+            .collect();
+        errors.sort_by_key(|(pos, _)| (pos.y, pos.x));
+        errors
+    }
+    /// Puts all cells with errors into the location list
+    pub fn list_errors(&self) {
+        let errors: Vec<CellPos> = self.errors().into_iter().map(|(pos, _)| pos).collect();
+        if errors.is_empty() {
+            util::notify("No cells have errors");
+            return;
+        }
+        self.set_loclist("Cells with errors", &errors);
+    }
+}
@@ -0,0 +1,158 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use bight::table::cell::CellPos;
+use nvim_oxi::{
+    self as nvim, Dictionary,
+    api::{
+        Window,
+        opts::{OptionOpts, OptionScope},
+    },
+};
+
+use crate::{
+    editor::{CELL_SEPARATOR, Editor, Viewport},
+    util::{self, get_as_bool_or, window_size},
+};
+
+static HEADERS: AtomicBool = AtomicBool::new(true);
+static CELL_WINBAR: AtomicBool = AtomicBool::new(false);
+
+const STATUSCOLUMN: &str = "%!v:lua.require'bight'.util.statuscolumn()";
+
+/// Applies the `headers` (enabled by default) and `cell_winbar` (disabled by default) setup
+/// options
+pub fn configure_headers(opts: &Dictionary) {
+    HEADERS.store(get_as_bool_or(opts, "headers", true), Ordering::Relaxed);
+    CELL_WINBAR.store(
+        get_as_bool_or(opts, "cell_winbar", false),
+        Ordering::Relaxed,
+    );
+}
+
+pub fn create_header_highlights() {
+    util::nvim_mlua()
+        .load(
+            r#"vim.api.nvim_set_hl(0, "BightHeader", { link = "LineNr", default = true })
+            vim.api.nvim_set_hl(0, "BightHeaderActive", { link = "CursorLineNr", default = true })
+            vim.api.nvim_set_hl(0, "BightCellError", { link = "DiagnosticError", default = true })
+            vim.api.nvim_set_hl(0, "BightPrecedent", { link = "DiffAdd", default = true })
+            vim.api.nvim_set_hl(0, "BightDependent", { link = "DiffChange", default = true })"#,
+        )
+        .exec()
+        .unwrap();
+}
+
+/// Letters of the column `x`, e.g. `AB`
+pub fn column_label(x: isize) -> String {
+    CellPos::from((x, 0))
+        .to_string()
+        .trim_end_matches(|c: char| c.is_ascii_digit())
+        .to_string()
+}
+
+/// Width of the row numbers shown in the statuscolumn of `window`. They take the whole width left
+/// of the text, which includes the sign and fold columns and 'numberwidth', except for the
+/// separating space, and grow if the rows from `origin_y` to `origin_y + height` need more digits.
+fn row_number_width(window: &Window, origin_y: isize, height: usize) -> usize {
+    let digits = (origin_y + height as isize).to_string().len();
+    util::text_offset(window).saturating_sub(1).max(digits)
+}
+
+/// Draws the column letters in the winbar and the row numbers in the statuscolumn of the current
+/// window, highlighting the current cell's column and row
+pub fn draw_headers(viewport: &Viewport) {
+    if CELL_WINBAR.load(Ordering::Relaxed) {
+        draw_cell_winbar();
+    }
+    if !HEADERS.load(Ordering::Relaxed) {
+        return;
+    }
+    let (display_width, _) = window_size();
+    let current = util::current_cell_pos();
+
+    let offset = util::text_offset(&nvim::api::get_current_win());
+    let mut winbar = format!("%#BightHeader#{}", " ".repeat(offset));
+    for x in (viewport.origin.x..).take(viewport.columns_to_fill(display_width)) {
+        let width = viewport.widths.get(x);
+        let label: String = column_label(x).chars().take(width).collect();
+        let label = format!("{label:^width$}");
+        if x == current.x {
+            winbar.push_str(&format!("%#BightHeaderActive#{label}%#BightHeader#"));
+        } else {
+            winbar.push_str(&label);
+        }
+        winbar.push_str(CELL_SEPARATOR);
+    }
+
+    let opts = current_window_opts();
+    if !CELL_WINBAR.load(Ordering::Relaxed) {
+        nvim::api::set_option_value("winbar", winbar, &opts).unwrap();
+    }
+    nvim::api::set_option_value("statuscolumn", STATUSCOLUMN, &opts).unwrap();
+    util::nvim_mlua()
+        .load("vim.api.nvim__redraw({ win = 0, statuscolumn = true })")
+        .exec()
+        .unwrap();
+}
+
+fn current_window_opts() -> OptionOpts {
+    OptionOpts::builder()
+        .win(nvim::api::get_current_win())
+        .scope(OptionScope::Local)
+        .build()
+}
+
+/// Address, source and value of the current cell formatted as a single line, e.g.
+/// `B3 │ =A3*2 │ 42`. Empty if the current buffer is not a bight buffer.
+pub fn cell_info() -> String {
+    let Some(editor) = Editor::of_existing_buffer(None) else {
+        return String::new();
+    };
+    let pos = util::current_cell_pos();
+    editor.evaluate();
+    let source = editor.get_source(pos);
+    let value = editor.get_value(pos);
+    format!(
+        "{pos} │ {} │ {}",
+        source.lines().next().unwrap_or(""),
+        value.lines().next().unwrap_or("")
+    )
+}
+
+/// Shows `cell_info` in the winbar of the current window, highlighting errors with
+/// `BightCellError`. Takes the place of the column header.
+fn draw_cell_winbar() {
+    let pos = util::current_cell_pos();
+    let is_err = Editor::of_existing_buffer(None).is_some_and(|editor| editor.is_err(pos));
+    let group = if is_err { "BightCellError" } else { "WinBar" };
+    let winbar = format!("%#{group}#{}", cell_info().replace('%', "%%"));
+    nvim::api::set_option_value("winbar", winbar, &current_window_opts()).unwrap();
+}
+
+/// Contents of the statuscolumn for the line `v:lnum` of the window being drawn
+pub fn statuscolumn() -> String {
+    let window = nvim::api::get_var::<i32>("statusline_winid")
+        .map(Window::from)
+        .unwrap_or_else(|_| nvim::api::get_current_win());
+    let Some(editor) = window
+        .get_buf()
+        .ok()
+        .and_then(|buffer| Editor::of_existing_buffer(Some(buffer.handle())))
+    else {
+        return String::new();
+    };
+    if nvim::api::get_vvar::<i64>("virtnum").unwrap_or_default() != 0 {
+        return String::new();
+    }
+    let lnum = nvim::api::get_vvar::<i64>("lnum").unwrap_or(1) as usize;
+    let height = window.get_height().unwrap_or_default() as usize;
+    let origin_y = editor.viewport().origin.y;
+
+    let width = row_number_width(&window, origin_y, height);
+    let row = origin_y + lnum as isize - 1;
+    let group = match window.get_cursor() {
+        Ok((line, _)) if line == lnum => "BightHeaderActive",
+        _ => "BightHeader",
+    };
+    format!("%#{group}#{row:>width$} ")
+}
@@ -0,0 +1,128 @@
+use std::sync::Arc;
+
+use bight::table::cell::CellPos;
+
+use crate::editor::ColumnWidths;
+
+/// A single source change of one cell. `None` means the cell had (or will have) no source.
+#[derive(Debug, Clone)]
+pub struct CellChange {
+    pub pos: CellPos,
+    pub old: Option<Arc<str>>,
+    pub new: Option<Arc<str>>,
+}
+
+/// A change recorded in the history
+#[derive(Debug, Clone)]
+pub enum Change {
+    Cell(CellChange),
+    /// The column widths changed along with the cells, e.g. when columns were inserted
+    Widths {
+        old: ColumnWidths,
+        new: ColumnWidths,
+    },
+}
+
+/// Undo/redo history of the editor's source table.
+///
+/// Changes are recorded into a pending step and become a single undoable step on `commit`, so an
+/// operation touching many cells (e.g. a visual paste) is undone at once.
+#[derive(Debug, Default)]
+pub struct History {
+    pending: Vec<Change>,
+    undo: Vec<Vec<Change>>,
+    redo: Vec<Vec<Change>>,
+}
+
+impl History {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    pub fn record(&mut self, change: Change) {
+        self.pending.push(change);
+    }
+    /// Finishes the pending step. Does nothing if no changes were recorded since the last commit.
+    pub fn commit(&mut self) {
+        if self.pending.is_empty() {
+            return;
+        }
+        self.undo.push(std::mem::take(&mut self.pending));
+        self.redo.clear();
+    }
+    /// Returns the changes of the last step in the order they should be reverted
+    pub fn undo(&mut self) -> Option<Vec<Change>> {
+        self.commit();
+        let step = self.undo.pop()?;
+        self.redo.push(step.clone());
+        Some(step.into_iter().rev().collect())
+    }
+    /// Returns the changes of the last undone step in the order they should be reapplied
+    pub fn redo(&mut self) -> Option<Vec<Change>> {
+        self.commit();
+        let step = self.redo.pop()?;
+        self.undo.push(step.clone());
+        Some(step)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn cell(x: isize, new: &str) -> Change {
+        Change::Cell(CellChange {
+            pos: (x, 0).into(),
+            old: None,
+            new: Some(new.into()),
+        })
+    }
+
+    fn positions(step: Option<Vec<Change>>) -> Vec<isize> {
+        step.unwrap()
+            .into_iter()
+            .map(|change| match change {
+                Change::Cell(change) => change.pos.x,
+                Change::Widths { .. } => -1,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn steps() {
+        let mut history = History::new();
+        history.record(cell(0, "a"));
+        history.record(cell(1, "b"));
+        history.commit();
+        history.record(cell(2, "c"));
+        history.commit();
+
+        assert_eq!(positions(history.undo()), vec![2]);
+        assert_eq!(positions(history.undo()), vec![1, 0]);
+        assert!(history.undo().is_none());
+        assert_eq!(positions(history.redo()), vec![0, 1]);
+    }
+
+    #[test]
+    fn commit_clears_redo() {
+        let mut history = History::new();
+        history.record(cell(0, "a"));
+        history.commit();
+        assert!(history.undo().is_some());
+
+        history.record(cell(1, "b"));
+        history.commit();
+        assert!(history.redo().is_none());
+        assert_eq!(positions(history.undo()), vec![1]);
+    }
+
+    #[test]
+    fn undo_commits_pending() {
+        let mut history = History::new();
+        history.record(cell(0, "a"));
+        history.commit();
+        history.record(cell(1, "b"));
+
+        assert_eq!(positions(history.undo()), vec![1]);
+        assert_eq!(positions(history.undo()), vec![0]);
+    }
+}
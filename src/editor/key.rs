@@ -1,222 +1,415 @@
-use std::sync::Arc;
+use std::sync::{LazyLock, Mutex};
 
-use crate::editor::{CELL_WIDTH, Editor, render_buffer};
+use crate::editor::{Editor, clipboard::yanked_block, render_buffer};
+use crate::reference::Axis;
 use crate::util::{
-    self, current_cell_pos, move_left, move_left_visual, move_right, move_right_visual,
+    self, count, current_cell_pos, get_as_bool_or, get_as_dictionary, move_cells,
+    move_cells_visual, notify_err, window_size,
 };
 
 use bight::table::slice::CellRange;
-use nvim_oxi::api::{Buffer, opts::SetKeymapOpts, types::Mode};
+use hashbrown::HashMap;
+use nvim_oxi::{
+    Dictionary, ObjectKind,
+    api::{Buffer, opts::SetKeymapOpts, types::Mode},
+};
 
-pub fn add_keymaps(buffer: &mut Buffer, editor: Editor) {
-    buffer
-        .set_keymap(
-            Mode::Normal,
-            "l",
-            "",
-            &SetKeymapOpts::builder().callback(|()| move_right()).build(),
-        )
-        .unwrap();
+/// Default lhs of every keymap action. Actions prefixed with `visual_` are mapped in visual mode.
+pub const DEFAULT_KEYMAPS: &[(&str, &str)] = &[
+    ("move_left", "h"),
+    ("move_right", "l"),
+    ("move_up", "k"),
+    ("move_down", "j"),
+    ("visual_move_left", "h"),
+    ("visual_move_right", "l"),
+    ("visual_move_up", "k"),
+    ("visual_move_down", "j"),
+    ("visual_mode", "v"),
+    ("widen_column", ">"),
+    ("narrow_column", "<"),
+    ("fit_column", "="),
+    ("scroll_half_down", "<C-d>"),
+    ("scroll_half_up", "<C-u>"),
+    ("scroll_down", "<C-e>"),
+    ("scroll_up", "<C-y>"),
+    ("scroll_cursor_top", "zt"),
+    ("scroll_cursor_center", "zz"),
+    ("scroll_cursor_bottom", "zb"),
+    ("insert_row_below", "o"),
+    ("insert_row_above", "O"),
+    ("goto_cell", "go"),
+    ("goto_reference", "gd"),
+    ("next_match", "n"),
+    ("previous_match", "N"),
+    ("undo", "u"),
+    ("redo", "<C-r>"),
+    ("yank_source", "yy"),
+    ("delete", "dd"),
+    ("change", "cc"),
+    ("yank_value", "Y"),
+    ("visual_yank", "y"),
+    ("visual_yank_values", "Y"),
+    ("edit_cell", "I"),
+    ("paste", "p"),
+    ("visual_delete", "d"),
+    ("visual_paste", "p"),
+    ("visual_fill_down", "<C-d>"),
+    ("visual_fill_right", "<C-r>"),
+    ("visual_sort", "s"),
+    ("visual_sort_descending", "S"),
+];
+
+static KEYMAPS: LazyLock<Mutex<HashMap<&'static str, Option<String>>>> = LazyLock::new(|| {
+    Mutex::new(
+        DEFAULT_KEYMAPS
+            .iter()
+            .map(|&(action, lhs)| (action, Some(String::from(lhs))))
+            .collect(),
+    )
+});
+
+/// Applies `default_keys` and `keys` from setup options.
+///
+/// `default_keys = false` disables all default keymaps. `keys` maps action names to lhs strings,
+/// or to `false` to disable the action.
+pub fn configure_keymaps(opts: &Dictionary) {
+    let mut keymaps = KEYMAPS.lock().unwrap();
+    if !get_as_bool_or(opts, "default_keys", true) {
+        keymaps.values_mut().for_each(|lhs| *lhs = None);
+    }
+    let Some(keys) = get_as_dictionary(opts, "keys") else {
+        return;
+    };
+    for (action, lhs) in keys.iter() {
+        let action = action.to_string_lossy();
+        let Some((&action, _)) = keymaps.get_key_value(action.as_ref()) else {
+            notify_err(&format!("Unknown bight keymap action {action}"));
+            continue;
+        };
+        let lhs = match lhs.kind() {
+            ObjectKind::Boolean if !unsafe { lhs.as_boolean_unchecked() } => None,
+            ObjectKind::String => Some(unsafe { lhs.as_nvim_str_unchecked() }.to_string()),
+            _ => {
+                notify_err(&format!(
+                    "Keymap for bight action {action} must be a string or false"
+                ));
+                continue;
+            }
+        };
+        keymaps.insert(action, lhs);
+    }
+}
+
+fn keymap_lhs(action: &str) -> Option<String> {
+    KEYMAPS.lock().unwrap().get(action).cloned().flatten()
+}
+
+fn map(buffer: &mut Buffer, mode: Mode, action: &str, callback: impl FnMut(()) + 'static) {
+    let Some(lhs) = keymap_lhs(action) else {
+        return;
+    };
     buffer
         .set_keymap(
-            Mode::VisualSelect,
-            "l",
+            mode,
+            &lhs,
             "",
             &SetKeymapOpts::builder()
-                .callback(|()| move_right_visual())
+                .callback(callback)
+                .noremap(true)
                 .build(),
         )
         .unwrap();
-    buffer
-        .set_keymap(
-            Mode::Normal,
-            "h",
-            "",
-            &SetKeymapOpts::builder().callback(|()| move_left()).build(),
-        )
-        .unwrap();
-    buffer
-        .set_keymap(
+}
+
+pub fn add_keymaps(buffer: &mut Buffer, editor: Editor) {
+    map(buffer, Mode::Normal, "move_right", |()| {
+        move_cells(count(), 0)
+    });
+    map(buffer, Mode::VisualSelect, "visual_move_right", |()| {
+        move_cells_visual(count(), 0)
+    });
+    map(buffer, Mode::Normal, "move_left", |()| {
+        move_cells(-count(), 0)
+    });
+    map(buffer, Mode::VisualSelect, "visual_move_left", |()| {
+        move_cells_visual(-count(), 0)
+    });
+    map(buffer, Mode::Normal, "move_down", |()| {
+        move_cells(0, count())
+    });
+    map(buffer, Mode::VisualSelect, "visual_move_down", |()| {
+        move_cells_visual(0, count())
+    });
+    map(buffer, Mode::Normal, "move_up", |()| {
+        move_cells(0, -count())
+    });
+    map(buffer, Mode::VisualSelect, "visual_move_up", |()| {
+        move_cells_visual(0, -count())
+    });
+    {
+        let editor = editor.clone();
+        map(buffer, Mode::Normal, "visual_mode", move |()| {
+            let width = editor.column_width(current_cell_pos().x);
+            util::nvim_mlua()
+                .load(format!(
+                    r#"vim.api.nvim_feedkeys(vim.keycode("<C-V>{width}l"), "n", false)"#
+                ))
+                .exec()
+                .unwrap();
+        });
+    }
+    {
+        let editor = editor.clone();
+        map(buffer, Mode::Normal, "widen_column", move |()| {
+            editor.resize_current_column(count())
+        });
+    }
+    {
+        let editor = editor.clone();
+        map(buffer, Mode::Normal, "narrow_column", move |()| {
+            editor.resize_current_column(-count())
+        });
+    }
+    {
+        let editor = editor.clone();
+        map(buffer, Mode::Normal, "fit_column", move |()| {
+            editor.fit_current_column()
+        });
+    }
+    {
+        let editor = editor.clone();
+        map(buffer, Mode::Normal, "insert_row_below", move |()| {
+            let mut pos = current_cell_pos();
+            editor.insert(Axis::Row, pos.y + 1, count());
+            pos.y += 1;
+            util::set_cursor_to_cell_pos(pos);
+        });
+    }
+    {
+        let editor = editor.clone();
+        map(buffer, Mode::Normal, "insert_row_above", move |()| {
+            editor.insert(Axis::Row, current_cell_pos().y, count())
+        });
+    }
+    map(buffer, Mode::Normal, "goto_cell", |()| {
+        if let Ok(address) = util::vim_fn("input").call::<String>("Go to cell: ")
+            && !address.is_empty()
+        {
+            util::goto_address(&address);
+        }
+    });
+    {
+        let editor = editor.clone();
+        map(buffer, Mode::Normal, "goto_reference", move |()| {
+            editor.goto_precedent()
+        });
+    }
+    {
+        let editor = editor.clone();
+        map(buffer, Mode::Normal, "next_match", move |()| {
+            editor.jump_to_match(true, count())
+        });
+    }
+    {
+        let editor = editor.clone();
+        map(buffer, Mode::Normal, "previous_match", move |()| {
+            editor.jump_to_match(false, count())
+        });
+    }
+    {
+        let editor = editor.clone();
+        map(buffer, Mode::Normal, "scroll_half_down", move |()| {
+            let (_, height) = window_size();
+            editor.scroll_rows(count() * (height as isize / 2).max(1));
+        });
+    }
+    {
+        let editor = editor.clone();
+        map(buffer, Mode::Normal, "scroll_half_up", move |()| {
+            let (_, height) = window_size();
+            editor.scroll_rows(-count() * (height as isize / 2).max(1));
+        });
+    }
+    {
+        let editor = editor.clone();
+        map(buffer, Mode::Normal, "scroll_down", move |()| {
+            editor.scroll_rows(count())
+        });
+    }
+    {
+        let editor = editor.clone();
+        map(buffer, Mode::Normal, "scroll_up", move |()| {
+            editor.scroll_rows(-count())
+        });
+    }
+    {
+        let editor = editor.clone();
+        map(buffer, Mode::Normal, "scroll_cursor_top", move |()| {
+            editor.scroll_current_row_to(0)
+        });
+    }
+    {
+        let editor = editor.clone();
+        map(buffer, Mode::Normal, "scroll_cursor_center", move |()| {
+            let (_, height) = window_size();
+            editor.scroll_current_row_to(height as isize / 2);
+        });
+    }
+    {
+        let editor = editor.clone();
+        map(buffer, Mode::Normal, "scroll_cursor_bottom", move |()| {
+            let (_, height) = window_size();
+            editor.scroll_current_row_to(height as isize - 1);
+        });
+    }
+    {
+        let editor = editor.clone();
+        map(buffer, Mode::Normal, "undo", move |()| editor.undo());
+    }
+    {
+        let editor = editor.clone();
+        map(buffer, Mode::Normal, "redo", move |()| editor.redo());
+    }
+    {
+        let editor = editor.clone();
+        map(buffer, Mode::Normal, "yank_source", move |()| {
+            editor.yank_current_source()
+        });
+    }
+    {
+        let editor = editor.clone();
+        map(buffer, Mode::Normal, "delete", move |()| {
+            editor.yank_current_source();
+            editor.set_source(util::current_cell_pos(), String::from(""));
+            editor.render();
+        });
+    }
+    {
+        let editor = editor.clone();
+        map(buffer, Mode::Normal, "change", move |()| {
+            editor.yank_current_source();
+            editor.set_source(util::current_cell_pos(), String::from(""));
+            editor.render();
+            nvim_oxi::api::command("startinsert").unwrap();
+        });
+    }
+    {
+        let editor = editor.clone();
+        map(buffer, Mode::Normal, "yank_value", move |()| {
+            editor.yank_current_value()
+        });
+    }
+    {
+        let editor = editor.clone();
+        map(buffer, Mode::VisualSelect, "visual_yank", move |()| {
+            editor.yank_current_source_range()
+        });
+    }
+    {
+        let editor = editor.clone();
+        map(
+            buffer,
             Mode::VisualSelect,
-            "h",
-            "",
-            &SetKeymapOpts::builder()
-                .callback(|()| move_left_visual())
-                .build(),
-        )
-        .unwrap();
-    buffer
-        .set_keymap(
-            Mode::Normal,
-            "v",
-            &format!("<C-V>{}l", CELL_WIDTH),
-            &SetKeymapOpts::builder().noremap(true).build(),
-        )
-        .unwrap();
+            "visual_yank_values",
+            move |()| editor.yank_current_value_range_as_csv(),
+        );
+    }
     {
         let editor = editor.clone();
-        buffer
-            .set_keymap(
-                Mode::Normal,
-                "yy",
-                "",
-                &SetKeymapOpts::builder()
-                    .callback(move |()| editor.yank_current_source())
-                    .build(),
-            )
-            .unwrap();
-    }
-    {
-        let editor = editor.clone();
-        buffer
-            .set_keymap(
-                Mode::Normal,
-                "dd",
-                "",
-                &SetKeymapOpts::builder()
-                    .callback(move |()| {
-                        editor.yank_current_source();
-                        editor.set_source(util::current_cell_pos(), String::from(""));
-                        editor.render();
-                    })
-                    .build(),
-            )
-            .unwrap();
-    }
-    {
-        let editor = editor.clone();
-        buffer
-            .set_keymap(
-                Mode::Normal,
-                "cc",
-                "",
-                &SetKeymapOpts::builder()
-                    .callback(move |()| {
-                        editor.yank_current_source();
-                        editor.set_source(util::current_cell_pos(), String::from(""));
-                        editor.render();
-                        nvim_oxi::api::command("startinsert").unwrap();
-                    })
-                    .build(),
-            )
-            .unwrap();
-    }
-    {
-        let editor = editor.clone();
-        buffer
-            .set_keymap(
-                Mode::Normal,
-                "Y",
-                "",
-                &SetKeymapOpts::builder()
-                    .callback(move |()| editor.yank_current_value())
-                    .build(),
-            )
-            .unwrap();
-    }
-    {
-        let editor = editor.clone();
-        buffer
-            .set_keymap(
-                Mode::VisualSelect,
-                "Y",
-                "",
-                &SetKeymapOpts::builder()
-                    .callback(move |()| editor.yank_current_value_range_as_csv())
-                    .build(),
-            )
-            .unwrap();
-    }
-    {
-        let editor = editor.clone();
-        buffer
-            .set_keymap(
-                Mode::Normal,
-                "I",
-                "",
-                &SetKeymapOpts::builder()
-                    .callback(move |()| {
-                        let pos = current_cell_pos();
-                        editor.start_editing_cell(pos);
-                    })
-                    .build(),
-            )
-            .unwrap();
-    }
-    {
-        let editor = editor.clone();
-        buffer
-            .set_keymap(
-                Mode::Normal,
-                "p",
-                "",
-                &SetKeymapOpts::builder()
-                    .callback(move |()| {
-                        {
-                            let mut editor = editor.lock().unwrap();
-                            let pos = current_cell_pos();
-                            editor.visual_start = pos;
-                            let source = editor.clipboard.get();
-                            editor.table.set_source(pos, source);
-                        }
-                        editor.render();
-                    })
-                    .noremap(true)
-                    .build(),
-            )
-            .unwrap();
+        map(buffer, Mode::Normal, "edit_cell", move |()| {
+            let pos = current_cell_pos();
+            editor.start_editing_cell(pos);
+        });
     }
-    let cb = {
+    {
         let editor = editor.clone();
-        move |()| {
+        map(buffer, Mode::Normal, "paste", move |()| {
+            {
+                let pos = current_cell_pos();
+                let mut editor = editor.lock().unwrap();
+                editor.visual_start = pos;
+                editor.paste(pos);
+                editor.commit_history();
+            }
+            editor.render();
+        });
+    }
+    {
+        let editor = editor.clone();
+        map(buffer, Mode::VisualSelect, "visual_delete", move |()| {
             let start = editor.state().visual_start;
             let mut end = current_cell_pos();
             end.x += 1;
             end.y += 1;
             let slice = CellRange::from((start, end));
 
-            for row in slice.rows() {
-                for col in slice.columns() {
-                    let mut pos = slice.start;
-                    pos.x += col;
-                    pos.y += row;
-                    editor.state().table.set_source::<Arc<str>>(pos, None);
+            {
+                let mut state = editor.state();
+                for row in slice.rows() {
+                    for col in slice.columns() {
+                        let mut pos = slice.start;
+                        pos.x += col;
+                        pos.y += row;
+                        state.set_source(pos, None);
+                    }
                 }
+                state.commit_history();
             }
             editor.render();
             render_buffer(&editor);
-        }
-    };
-    buffer
-        .set_keymap(
-            Mode::VisualSelect,
-            "d",
-            "",
-            &SetKeymapOpts::builder().callback(cb).noremap(true).build(),
-        )
-        .unwrap();
+        });
+    }
+    {
+        let editor = editor.clone();
+        map(buffer, Mode::VisualSelect, "visual_fill_down", move |()| {
+            editor.fill_current_visual_range(Axis::Row)
+        });
+    }
+    {
+        let editor = editor.clone();
+        map(buffer, Mode::VisualSelect, "visual_fill_right", move |()| {
+            editor.fill_current_visual_range(Axis::Column)
+        });
+    }
+    {
+        let editor = editor.clone();
+        map(buffer, Mode::VisualSelect, "visual_sort", move |()| {
+            editor.sort_current_visual_range(false)
+        });
+    }
     {
-        let cb = move |()| {
-            let range = editor.get_current_visual_range();
+        let editor = editor.clone();
+        map(
+            buffer,
+            Mode::VisualSelect,
+            "visual_sort_descending",
+            move |()| editor.sort_current_visual_range(true),
+        );
+    }
+    map(buffer, Mode::VisualSelect, "visual_paste", move |()| {
+        let range = editor.get_current_visual_range();
 
-            let source = editor.state().clipboard.get();
-            for row in range.rows() {
-                for col in range.columns() {
-                    let mut pos = range.start;
-                    pos.x += col;
-                    pos.y += row;
-                    editor.state().table.set_source(pos, source.clone());
+        {
+            let mut state = editor.state();
+            let source = state.clipboard.get();
+            let block = source.as_deref().and_then(yanked_block);
+            if let Some(block) = block.as_ref().filter(|block| !block.is_single_cell()) {
+                state.paste_block(range.start, block);
+            } else {
+                for row in range.rows() {
+                    for col in range.columns() {
+                        let mut pos = range.start;
+                        pos.x += col;
+                        pos.y += row;
+                        match &block {
+                            Some(block) => state.paste_block(pos, block),
+                            None => state.set_source(pos, source.clone()),
+                        }
+                    }
                 }
             }
-            editor.render();
-        };
-        buffer
-            .set_keymap(
-                Mode::VisualSelect,
-                "p",
-                "",
-                &SetKeymapOpts::builder().callback(cb).noremap(true).build(),
-            )
-            .unwrap();
-    }
+            state.commit_history();
+        }
+        editor.render();
+    });
 }
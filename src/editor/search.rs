@@ -0,0 +1,251 @@
+use std::sync::Arc;
+
+use crate::{
+    editor::Editor,
+    substitute::Substitution,
+    util::{self, cursor_position, nvim_mlua, vim_fn},
+};
+use bight::{
+    evaluator::TableValue,
+    table::{CellRange, Table, cell::CellPos},
+};
+use nvim_oxi::mlua::Table as LuaTable;
+
+/// Whether `text` contains `pattern`. The search ignores case unless the pattern has uppercase
+/// letters, like with 'smartcase'.
+pub fn smartcase_contains(text: &str, pattern: &str) -> bool {
+    if pattern.chars().any(char::is_uppercase) {
+        text.contains(pattern)
+    } else {
+        text.to_lowercase().contains(&pattern.to_lowercase())
+    }
+}
+
+impl Editor {
+    /// Positions of the cells whose full source or evaluated value contains `pattern`, ordered by
+    /// rows
+    pub fn find(&self, pattern: &str) -> Vec<CellPos> {
+        let mut state = self.state();
+        state.evaluate();
+        let mut found: Vec<CellPos> = state
+            .sources
+            .iter()
+            .filter(|&(&pos, source)| {
+                let value = state.table.get(pos).unwrap_or(&TableValue::Empty);
+                smartcase_contains(source, pattern)
+                    || smartcase_contains(&value.to_string(), pattern)
+            })
+            .map(|(&pos, _)| pos)
+            .collect();
+        found.sort_by_key(|pos| (pos.y, pos.x));
+        found
+    }
+    /// Makes `matches` the targets of `jump_to_match`
+    pub fn set_matches(&self, matches: Vec<CellPos>) {
+        self.state().matches = matches;
+    }
+    /// Moves the cursor `count` matches forward (or backward if `forward` is false) from the
+    /// current cell, wrapping around the table
+    pub fn jump_to_match(&self, forward: bool, count: isize) {
+        let matches = self.state().matches.clone();
+        if matches.is_empty() {
+            util::notify_err("No matches, search with :BightFind");
+            return;
+        }
+        let current = util::current_cell_pos();
+        let key = |pos: &CellPos| (pos.y, pos.x);
+        let len = matches.len() as isize;
+        // Index of the first match after the current cell, or of the last one before it
+        let after = matches.partition_point(|pos| key(pos) <= key(&current)) as isize;
+        let before = matches.partition_point(|pos| key(pos) < key(&current)) as isize - 1;
+        let index = if forward {
+            after + count - 1
+        } else {
+            before - count + 1
+        };
+        util::set_cursor_to_cell_pos(matches[index.rem_euclid(len) as usize]);
+    }
+    /// Applies the substitution to the sources of the cells in `range` (the whole table if
+    /// `None`) as a single undoable change and renders the table once. Returns the number of
+    /// changed cells, or of the cells that would change if the substitution only counts.
+    pub fn replace(
+        &self,
+        substitution: &Substitution,
+        range: Option<CellRange>,
+    ) -> anyhow::Result<usize> {
+        let sources: Vec<(CellPos, Arc<str>)> = self
+            .state()
+            .sources
+            .iter()
+            .filter(|&(&pos, source)| {
+                substitution.kind.matches(source)
+                    && range.is_none_or(|range| {
+                        range.columns().contains(&(pos.x - range.start.x))
+                            && range.rows().contains(&(pos.y - range.start.y))
+                    })
+            })
+            .map(|(&pos, source)| (pos, source.clone()))
+            .collect();
+
+        let substitute = vim_fn("substitute");
+        let pattern = substitution.vim_pattern();
+        let flags = if substitution.global { "g" } else { "" };
+        let mut changes = Vec::new();
+        for (pos, source) in sources {
+            let new = substitute.call::<String>((
+                source.as_ref(),
+                pattern.as_str(),
+                substitution.replacement.as_str(),
+                flags,
+            ))?;
+            if new != source.as_ref() {
+                changes.push((pos, new));
+            }
+        }
+        let count = changes.len();
+        if substitution.count_only || count == 0 {
+            return Ok(count);
+        }
+        {
+            let mut state = self.state();
+            for (pos, source) in changes {
+                state.set_source(pos, Some(Arc::from(source)));
+            }
+            state.commit_history();
+        }
+        self.render();
+        Ok(count)
+    }
+    /// Replaces the quickfix list with the matches, showing each cell's address, source and value
+    pub fn set_quickfix(&self, title: &str, matches: &[CellPos]) {
+        let what = self.cell_list(title, matches);
+        vim_fn("setqflist")
+            .call::<()>((nvim_mlua().create_table().unwrap(), " ", what))
+            .unwrap();
+        let id = quickfix_entry(
+            &vim_fn("getqflist")
+                .call::<LuaTable>(current_entry())
+                .unwrap(),
+        )
+        .0;
+        self.state().cell_lists.insert(id, CellList::new(matches));
+    }
+    /// Replaces the location list of the current window with the cells, like `set_quickfix`
+    pub fn set_loclist(&self, title: &str, cells: &[CellPos]) {
+        let what = self.cell_list(title, cells);
+        vim_fn("setloclist")
+            .call::<()>((0, nvim_mlua().create_table().unwrap(), " ", what))
+            .unwrap();
+        let id = quickfix_entry(
+            &vim_fn("getloclist")
+                .call::<LuaTable>((0, current_entry()))
+                .unwrap(),
+        )
+        .0;
+        self.state().cell_lists.insert(id, CellList::new(cells));
+    }
+    /// Quickfix list properties (`:h setqflist-what`) listing the cells. Entries point at the
+    /// cells' positions in the buffer with the current view, cells outside of it at its edge.
+    fn cell_list(&self, title: &str, matches: &[CellPos]) -> LuaTable {
+        let (buffer, viewport) = {
+            let state = self.state();
+            (state.buffer.clone(), state.viewport.clone())
+        };
+        let line_count = buffer.line_count().unwrap_or(1).max(1);
+        let lua = nvim_mlua();
+        let items = lua.create_table().unwrap();
+        for &pos in matches {
+            let source = self.get_source(pos);
+            let value = self.get_value(pos);
+            let (line, col) = cursor_position(&viewport, pos);
+            let item = lua.create_table().unwrap();
+            item.set("bufnr", buffer.handle()).unwrap();
+            item.set("lnum", line.min(line_count)).unwrap();
+            item.set("col", col + 1).unwrap();
+            item.set("module", pos.to_string()).unwrap();
+            item.set(
+                "text",
+                format!(
+                    "{} │ {}",
+                    source.lines().next().unwrap_or(""),
+                    value.lines().next().unwrap_or("")
+                ),
+            )
+            .unwrap();
+            items.push(item).unwrap();
+        }
+        let what = lua.create_table().unwrap();
+        what.set("title", title).unwrap();
+        what.set("items", items).unwrap();
+        what
+    }
+    /// Moves the cursor to the cell of the current entry of a cell list after a jump to the entry
+    /// (`:cnext`, `:lnext`, `<CR>` in the quickfix window). The entries' buffer positions are
+    /// stale once the view scrolls, so the jump alone may land on another cell.
+    pub fn follow_cell_list(&self) {
+        let quickfix = vim_fn("getqflist").call::<LuaTable>(current_entry());
+        let loclist = vim_fn("getloclist").call::<LuaTable>((0, current_entry()));
+        for info in [quickfix, loclist] {
+            let (id, index) = quickfix_entry(&info.unwrap());
+            let pos = {
+                let mut state = self.state();
+                let Some(list) = state.cell_lists.get_mut(&id) else {
+                    continue;
+                };
+                if list.followed == index {
+                    continue;
+                }
+                list.followed = index;
+                list.cells.get(index.saturating_sub(1)).copied()
+            };
+            if let Some(pos) = pos {
+                util::set_cursor_to_cell_pos(pos);
+                return;
+            }
+        }
+    }
+}
+
+/// A quickfix or location list of cells made by the editor
+pub struct CellList {
+    cells: Vec<CellPos>,
+    /// Index of the last entry whose cell the cursor was moved to, starting at 1
+    followed: usize,
+}
+
+impl CellList {
+    fn new(cells: &[CellPos]) -> Self {
+        Self {
+            cells: cells.to_vec(),
+            followed: 1,
+        }
+    }
+}
+
+/// `getqflist` and `getloclist` argument requesting the id of the list and the index of its current entry
+fn current_entry() -> LuaTable {
+    let what = nvim_mlua().create_table().unwrap();
+    what.set("id", 0).unwrap();
+    what.set("idx", 0).unwrap();
+    what
+}
+
+/// The list id and the current entry's index returned for `current_entry`
+fn quickfix_entry(info: &LuaTable) -> (u64, usize) {
+    (
+        info.get("id").unwrap_or_default(),
+        info.get("idx").unwrap_or_default(),
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn smartcase() {
+        assert!(smartcase_contains("Total Price", "price"));
+        assert!(smartcase_contains("Total Price", "Price"));
+        assert!(!smartcase_contains("Total price", "Price"));
+    }
+}
@@ -0,0 +1,145 @@
+use bight::table::cell::CellPos;
+use hashbrown::HashMap;
+
+use crate::{
+    editor::{CELL_SEPARATOR, CELL_WIDTH},
+    reference::{Axis, move_pos},
+};
+
+/// Display widths of the table's columns. Columns without an explicitly set width are
+/// `CELL_WIDTH` wide.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ColumnWidths {
+    widths: HashMap<isize, usize>,
+}
+
+impl ColumnWidths {
+    pub fn get(&self, x: isize) -> usize {
+        self.widths.get(&x).copied().unwrap_or(CELL_WIDTH)
+    }
+    /// Width of the column including the separator after it
+    pub fn unit_width(&self, x: isize) -> usize {
+        self.get(x) + CELL_SEPARATOR.len()
+    }
+    pub fn set(&mut self, x: isize, width: usize) {
+        let width = width.max(1);
+        if width == CELL_WIDTH {
+            self.widths.remove(&x);
+        } else {
+            self.widths.insert(x, width);
+        }
+    }
+    /// Moves the widths along with the columns when `count` columns are inserted before `at`, or
+    /// deleted starting from `at` if `count` is negative
+    pub fn shift(&mut self, at: isize, count: isize) {
+        self.widths = self
+            .widths
+            .drain()
+            .filter_map(|(x, width)| {
+                move_pos((x, 0).into(), Axis::Column, at, count).map(|pos| (pos.x, width))
+            })
+            .collect();
+    }
+}
+
+/// The part of the table shown in the editor's buffer. The first buffer line and column show the
+/// cell at `origin`.
+#[derive(Debug, Clone, Default)]
+pub struct Viewport {
+    pub origin: CellPos,
+    pub widths: ColumnWidths,
+}
+
+impl Viewport {
+    /// Buffer column at which the column `x` starts. Columns to the left of the origin start at 0.
+    pub fn column_offset(&self, x: isize) -> usize {
+        (self.origin.x..x).map(|x| self.widths.unit_width(x)).sum()
+    }
+    /// Table column shown at the buffer column `col`
+    pub fn column_at(&self, col: usize) -> isize {
+        let mut x = self.origin.x;
+        let mut end = self.widths.unit_width(x);
+        while end <= col {
+            x += 1;
+            end += self.widths.unit_width(x);
+        }
+        x
+    }
+    /// Number of columns needed to fill `display_width` buffer columns, including the last
+    /// partially visible one
+    pub fn columns_to_fill(&self, display_width: usize) -> usize {
+        let mut count = 0;
+        let mut filled = 0;
+        while filled < display_width {
+            filled += self.widths.unit_width(self.origin.x + count as isize);
+            count += 1;
+        }
+        count.max(1)
+    }
+    /// Moves the origin by the smallest amount so that `pos` is fully inside of a window that is
+    /// `display_width` columns wide and `height` lines high. Returns true if the origin was
+    /// changed.
+    pub fn scroll_to(&mut self, pos: CellPos, display_width: usize, height: usize) -> bool {
+        let old = self.origin;
+        let height = height.max(1) as isize;
+
+        if pos.x < self.origin.x {
+            self.origin.x = pos.x;
+        } else {
+            while self.origin.x < pos.x
+                && self.column_offset(pos.x) + self.widths.get(pos.x) > display_width
+            {
+                self.origin.x += 1;
+            }
+        }
+        if pos.y < self.origin.y {
+            self.origin.y = pos.y;
+        } else if pos.y >= self.origin.y + height {
+            self.origin.y = pos.y - height + 1;
+        }
+        self.clamp();
+
+        old != self.origin
+    }
+    pub fn set_origin(&mut self, origin: CellPos) -> bool {
+        let old = self.origin;
+        self.origin = origin;
+        self.clamp();
+        old != self.origin
+    }
+    fn clamp(&mut self) {
+        self.origin.x = self.origin.x.max(0);
+        self.origin.y = self.origin.y.max(0);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn column_math() {
+        let mut viewport = Viewport::default();
+        viewport.widths.set(1, 3);
+
+        assert_eq!(viewport.column_offset(0), 0);
+        assert_eq!(viewport.column_offset(2), CELL_WIDTH + 1 + 3 + 1);
+        assert_eq!(viewport.column_at(CELL_WIDTH), 0);
+        assert_eq!(viewport.column_at(CELL_WIDTH + 1), 1);
+        assert_eq!(viewport.column_at(CELL_WIDTH + 5), 2);
+
+        viewport.origin.x = 1;
+        assert_eq!(viewport.column_offset(2), 4);
+        assert_eq!(viewport.column_at(0), 1);
+    }
+
+    #[test]
+    fn scroll_to() {
+        let mut viewport = Viewport::default();
+        assert!(!viewport.scroll_to((1, 3).into(), 20, 10));
+        assert!(viewport.scroll_to((4, 12).into(), 20, 10));
+        assert_eq!(viewport.origin, (3, 3).into());
+        assert!(viewport.scroll_to((0, 0).into(), 20, 10));
+        assert_eq!(viewport.origin, (0, 0).into());
+    }
+}
@@ -0,0 +1,117 @@
+use std::{path::Path, str::FromStr};
+
+use crate::csv::{self, CsvOptions};
+
+/// A format that a range of cells can be exported to
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExportFormat {
+    Csv,
+    Tsv,
+    Markdown,
+    Html,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct ExportFormatParseError;
+
+impl FromStr for ExportFormat {
+    type Err = ExportFormatParseError;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(match s.to_lowercase().as_str() {
+            "csv" => Self::Csv,
+            "tsv" => Self::Tsv,
+            "md" | "markdown" => Self::Markdown,
+            "html" | "htm" => Self::Html,
+            _ => return Err(ExportFormatParseError),
+        })
+    }
+}
+
+impl ExportFormat {
+    /// Guesses the format from the file's extension
+    pub fn from_path(path: &Path) -> Option<Self> {
+        path.extension()?.to_str()?.parse().ok()
+    }
+
+    pub fn format(self, records: &[Vec<String>]) -> String {
+        match self {
+            Self::Csv => csv::write(records, &CsvOptions::default()),
+            Self::Tsv => csv::write(
+                records,
+                &CsvOptions {
+                    delimiter: '\t',
+                    ..Default::default()
+                },
+            ),
+            Self::Markdown => format_markdown(records),
+            Self::Html => format_html(records),
+        }
+    }
+}
+
+/// Formats records as a Markdown pipe table with the first record as the header
+fn format_markdown(records: &[Vec<String>]) -> String {
+    let Some(header) = records.first() else {
+        return String::new();
+    };
+    let row = |record: &Vec<String>| {
+        let cells: Vec<_> = record
+            .iter()
+            .map(|field| field.replace('|', "\\|").replace('\n', "<br>"))
+            .collect();
+        format!("| {} |\n", cells.join(" | "))
+    };
+
+    let mut out = row(header);
+    out.push_str(&format!("|{}\n", " --- |".repeat(header.len())));
+    records[1..]
+        .iter()
+        .for_each(|record| out.push_str(&row(record)));
+    out
+}
+
+fn escape_html(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+fn format_html(records: &[Vec<String>]) -> String {
+    let mut out = String::from("<table>\n");
+    for record in records {
+        out.push_str("  <tr>");
+        for field in record {
+            out.push_str(&format!("<td>{}</td>", escape_html(field)));
+        }
+        out.push_str("</tr>\n");
+    }
+    out.push_str("</table>\n");
+    out
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn markdown() {
+        let records = vec![
+            vec!["name".to_string(), "a|b".to_string()],
+            vec!["x".to_string(), "1".to_string()],
+        ];
+        assert_eq!(
+            ExportFormat::Markdown.format(&records),
+            "| name | a\\|b |\n| --- | --- |\n| x | 1 |\n"
+        );
+    }
+
+    #[test]
+    fn html() {
+        let records = vec![vec!["<b>".to_string()]];
+        assert_eq!(
+            ExportFormat::Html.format(&records),
+            "<table>\n  <tr><td>&lt;b&gt;</td></tr>\n</table>\n"
+        );
+    }
+}
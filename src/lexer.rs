@@ -0,0 +1,128 @@
+/// Kind of a piece of Lua source
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TokenKind {
+    /// A name or a keyword. Names may contain `$` to allow anchored references like `$A$1`.
+    Name,
+    /// Whitespace or a comment
+    Space,
+    /// A string, a number or a punctuation character
+    Other,
+}
+
+/// A piece of a Lua source, `source[start..end]`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Token {
+    pub kind: TokenKind,
+    pub start: usize,
+    pub end: usize,
+}
+
+impl Token {
+    pub fn text(self, source: &str) -> &str {
+        &source[self.start..self.end]
+    }
+}
+
+pub fn is_name_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || c == '_' || c == '$'
+}
+
+/// Splits the Lua source into tokens. Strings, comments and numbers are single tokens, and so is
+/// every punctuation character. Unterminated strings and comments extend to the end of `source`.
+pub fn tokens(source: &str) -> Vec<Token> {
+    let mut tokens = Vec::new();
+    let mut i = 0;
+    while i < source.len() {
+        let rest = &source[i..];
+        let c = rest.chars().next().unwrap();
+        let (kind, len) = if let Some(comment) = rest.strip_prefix("--") {
+            let len = match long_bracket(comment) {
+                Some((open, level)) => 2 + long_string_len(comment, open, level),
+                None => rest.find('\n').unwrap_or(rest.len()),
+            };
+            (TokenKind::Space, len)
+        } else if c.is_whitespace() {
+            let len = rest
+                .find(|c: char| !c.is_whitespace())
+                .unwrap_or(rest.len());
+            (TokenKind::Space, len)
+        } else if let Some((open, level)) = long_bracket(rest) {
+            (TokenKind::Other, long_string_len(rest, open, level))
+        } else if c == '"' || c == '\'' {
+            (TokenKind::Other, quoted_string_len(rest, c))
+        } else if c.is_ascii_digit() {
+            let len = rest
+                .find(|c: char| !(c.is_ascii_alphanumeric() || c == '.' || c == '_'))
+                .unwrap_or(rest.len());
+            (TokenKind::Other, len)
+        } else if is_name_char(c) {
+            let len = rest.find(|c| !is_name_char(c)).unwrap_or(rest.len());
+            (TokenKind::Name, len)
+        } else {
+            (TokenKind::Other, c.len_utf8())
+        };
+        tokens.push(Token {
+            kind,
+            start: i,
+            end: i + len,
+        });
+        i += len;
+    }
+    tokens
+}
+
+/// Length of the long bracket opening (`[[`, `[==[`) at the start of `s`, and its level
+fn long_bracket(s: &str) -> Option<(usize, usize)> {
+    let rest = s.strip_prefix('[')?;
+    let level = rest.chars().take_while(|&c| c == '=').count();
+    rest[level..].starts_with('[').then_some((level + 2, level))
+}
+
+fn long_string_len(s: &str, open: usize, level: usize) -> usize {
+    let close = format!("]{}]", "=".repeat(level));
+    s[open..]
+        .find(&close)
+        .map_or(s.len(), |end| open + end + close.len())
+}
+
+fn quoted_string_len(s: &str, quote: char) -> usize {
+    let mut escaped = false;
+    for (i, c) in s.char_indices().skip(1) {
+        if escaped {
+            escaped = false;
+        } else if c == '\\' {
+            escaped = true;
+        } else if c == quote || c == '\n' {
+            return i + c.len_utf8();
+        }
+    }
+    s.len()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn split() {
+        let source = "x = \"a b\" -- c\n+ [[d]] .. 1e3";
+        let texts: Vec<(TokenKind, &str)> = tokens(source)
+            .into_iter()
+            .filter(|token| token.kind != TokenKind::Space)
+            .map(|token| (token.kind, token.text(source)))
+            .collect();
+        assert_eq!(
+            texts,
+            vec![
+                (TokenKind::Name, "x"),
+                (TokenKind::Other, "="),
+                (TokenKind::Other, "\"a b\""),
+                (TokenKind::Other, "+"),
+                (TokenKind::Other, "[[d]]"),
+                (TokenKind::Other, "."),
+                (TokenKind::Other, "."),
+                (TokenKind::Other, "1e3"),
+            ]
+        );
+    }
+}
@@ -1,11 +1,23 @@
+pub mod budget;
+pub mod csv;
 pub mod editor;
+pub mod export;
+pub mod lexer;
+pub mod reference;
+pub mod series;
+pub mod sort;
+pub mod substitute;
 pub mod util;
 
 use nvim_oxi::{self as nvim, Dictionary};
 
 use crate::{
-    editor::{Editor, attach_editor_autocmd},
-    util::{fn_object, get_as_bool},
+    csv::configure_csv,
+    editor::{
+        Editor, attach_editor_autocmd, configure_dependency_marks, configure_evaluation,
+        configure_headers, configure_keymaps, create_header_highlights,
+    },
+    util::fn_object,
 };
 
 fn create_filetype() {
@@ -23,9 +35,12 @@ fn create_filetype() {
 fn setup(opts: Option<Dictionary>) {
     let opts = opts.unwrap_or_default();
     create_filetype();
-    if get_as_bool(&opts, "default_keys") {
-        todo!();
-    }
+    configure_keymaps(&opts);
+    configure_headers(&opts);
+    configure_dependency_marks(&opts);
+    configure_csv(&opts);
+    configure_evaluation(&opts);
+    create_header_highlights();
     attach_editor_autocmd();
 }
 
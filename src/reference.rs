@@ -0,0 +1,386 @@
+use std::fmt::Display;
+
+use bight::table::cell::CellPos;
+use hashbrown::HashMap;
+
+use crate::lexer::{Token, TokenKind, tokens};
+
+/// Written in place of a reference that points outside of the table after rewriting
+pub const INVALID_REFERENCE: &str = "#REF!";
+
+/// Most letters of a column and digits of a row whose position fits into an `isize`. Longer
+/// names are not references, e.g. `abcdefghijklmnopqrstuvwxyz1`.
+const MAX_COLUMN_LETTERS: usize = 13;
+const MAX_ROW_DIGITS: usize = 18;
+
+/// An A1-style cell reference in a formula. `$` before the column or the row anchors it, so that
+/// it is not shifted when the formula is copied. Like in bight, the column is case-insensitive.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Reference {
+    pub pos: CellPos,
+    pub absolute_x: bool,
+    pub absolute_y: bool,
+    /// Whether the column was written in lowercase, e.g. `a3`
+    pub lowercase: bool,
+}
+
+impl Reference {
+    /// Parses a reference like `A3`, `$A$3`, `A$3`, `$A3` or `a3`
+    pub fn parse(s: &str) -> Option<Self> {
+        let (absolute_x, s) = match s.strip_prefix('$') {
+            Some(s) => (true, s),
+            None => (false, s),
+        };
+        let split = s.find(|c: char| !c.is_ascii_alphabetic())?;
+        let (column, row) = s.split_at(split);
+        let (absolute_y, row) = match row.strip_prefix('$') {
+            Some(row) => (true, row),
+            None => (false, row),
+        };
+        if column.is_empty()
+            || column.len() > MAX_COLUMN_LETTERS
+            || row.is_empty()
+            || row.len() > MAX_ROW_DIGITS
+            || !row.chars().all(|c| c.is_ascii_digit())
+        {
+            return None;
+        }
+        let pos = format!("{column}{row}").parse().ok()?;
+        Some(Self {
+            pos,
+            absolute_x,
+            absolute_y,
+            lowercase: column.chars().all(|c| c.is_ascii_lowercase()),
+        })
+    }
+    /// Moves the reference by `(dx, dy)`, keeping the anchored coordinates. Returns `None` if it
+    /// would point outside of the table.
+    pub fn shifted(self, dx: isize, dy: isize) -> Option<Self> {
+        let mut shifted = self;
+        if !self.absolute_x {
+            shifted.pos.x += dx;
+        }
+        if !self.absolute_y {
+            shifted.pos.y += dy;
+        }
+        (shifted.pos.x >= 0 && shifted.pos.y >= 0).then_some(shifted)
+    }
+}
+
+impl Display for Reference {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let pos = self.pos.to_string();
+        let split = pos.find(|c: char| c.is_ascii_digit()).unwrap_or(pos.len());
+        let (column, row) = pos.split_at(split);
+        let column = if self.lowercase {
+            column.to_ascii_lowercase()
+        } else {
+            column.to_string()
+        };
+        let x = if self.absolute_x { "$" } else { "" };
+        let y = if self.absolute_y { "$" } else { "" };
+        write!(f, "{x}{column}{y}{row}")
+    }
+}
+
+/// Parses a cell address typed by the user, e.g. `AB120` or `ab120`
+pub fn parse_address(s: &str) -> Option<CellPos> {
+    Reference::parse(s.trim()).map(|reference| reference.pos)
+}
+
+/// Rewrites every cell reference in the formula `source` with `f`. References for which `f`
+/// returns `None` are replaced with `INVALID_REFERENCE`. Sources that are not formulas (do not
+/// start with `=`) are returned unchanged, and so are strings, comments, field names, called
+/// functions (`LOG10(x)`) and names declared in the formula (`local a1`).
+pub fn map_references(source: &str, mut f: impl FnMut(Reference) -> Option<Reference>) -> String {
+    if !source.starts_with('=') {
+        return source.to_string();
+    }
+    let tokens = tokens(source);
+    let declared = declared_names(source, &tokens);
+    let mut result = String::with_capacity(source.len());
+    let mut code = tokens.iter().filter(|token| token.kind != TokenKind::Space);
+    let mut previous = [""; 2];
+    for token in tokens.iter() {
+        let text = token.text(source);
+        if token.kind == TokenKind::Space {
+            result.push_str(text);
+            continue;
+        }
+        code.next();
+        let following = code.clone().next().map_or("", |next| next.text(source));
+
+        let field = previous[1] == ":" || (previous[1] == "." && previous[0] != ".");
+        let call = following.starts_with(['(', '"', '\'', '{']) || following.starts_with("[[");
+        let reference = (token.kind == TokenKind::Name && !field && !call)
+            .then(|| Reference::parse(text))
+            .flatten()
+            .filter(|_| !declared.contains(&text));
+        match reference {
+            Some(reference) => match f(reference) {
+                Some(reference) => result.push_str(&reference.to_string()),
+                None => result.push_str(INVALID_REFERENCE),
+            },
+            None => result.push_str(text),
+        }
+        previous = [previous[1], text];
+    }
+    result
+}
+
+/// Names of the locals, loop variables and function parameters declared in the formula. They
+/// shadow the cells with the same address.
+fn declared_names<'a>(source: &'a str, tokens: &[Token]) -> Vec<&'a str> {
+    let mut declared = Vec::new();
+    // Whether the names that follow are declared, and whether a parameter list may follow
+    let mut declaring = false;
+    let mut parameters = false;
+    for token in tokens.iter().filter(|token| token.kind != TokenKind::Space) {
+        match (token.kind, token.text(source)) {
+            (TokenKind::Name, "local" | "for") => declaring = true,
+            (TokenKind::Name, "function") => parameters = true,
+            (TokenKind::Name, "in" | "do") => declaring = false,
+            (TokenKind::Name, name) if declaring => declared.push(name),
+            (TokenKind::Other, "(") if parameters => {
+                declaring = true;
+                parameters = false;
+            }
+            (TokenKind::Other, "," | "." | ":") | (TokenKind::Name, _) => {}
+            _ => {
+                declaring = false;
+                parameters = false;
+            }
+        }
+    }
+    declared
+}
+
+/// Cells referenced in the formula `source`, in the order of their first appearance. References
+/// computed at evaluation time (e.g. with `REL`) are not included.
+pub fn references(source: &str) -> Vec<CellPos> {
+    let mut found = Vec::new();
+    map_references(source, |reference| {
+        if !found.contains(&reference.pos) {
+            found.push(reference.pos);
+        }
+        Some(reference)
+    });
+    found
+}
+
+/// Finds reference cycles in the graph of formulas, where `edges` maps each formula cell to the
+/// cells it references. Every cycle is a path that starts and ends at the same cell, e.g.
+/// `[A1, B3, A1]`. Cells are visited in row order, so the result is deterministic.
+pub fn find_cycles(edges: &HashMap<CellPos, Vec<CellPos>>) -> Vec<Vec<CellPos>> {
+    #[derive(Clone, Copy, PartialEq, Eq)]
+    enum Visit {
+        InProgress,
+        Done,
+    }
+    let mut visits: HashMap<CellPos, Visit> = HashMap::new();
+    let mut cycles = Vec::new();
+    let mut starts: Vec<CellPos> = edges.keys().copied().collect();
+    starts.sort_by_key(|pos| (pos.y, pos.x));
+
+    for start in starts {
+        if visits.contains_key(&start) {
+            continue;
+        }
+        // Depth-first search with an explicit stack of cells and the index of their next edge
+        let mut stack = vec![(start, 0)];
+        visits.insert(start, Visit::InProgress);
+        while let Some(&(pos, edge)) = stack.last() {
+            stack.last_mut().unwrap().1 += 1;
+            let Some(&next) = edges.get(&pos).and_then(|e| e.get(edge)) else {
+                visits.insert(pos, Visit::Done);
+                stack.pop();
+                continue;
+            };
+            match visits.get(&next) {
+                None if edges.contains_key(&next) => {
+                    visits.insert(next, Visit::InProgress);
+                    stack.push((next, 0));
+                }
+                Some(Visit::InProgress) => {
+                    let from = stack.iter().position(|&(pos, _)| pos == next).unwrap();
+                    let mut cycle: Vec<CellPos> =
+                        stack[from..].iter().map(|&(pos, _)| pos).collect();
+                    cycle.push(next);
+                    cycles.push(cycle);
+                }
+                _ => {}
+            }
+        }
+    }
+    cycles
+}
+
+/// Formats a cycle as a path, e.g. `A1 -> B3 -> A1`
+pub fn format_cycle(cycle: &[CellPos]) -> String {
+    cycle
+        .iter()
+        .map(|pos| pos.to_string())
+        .collect::<Vec<_>>()
+        .join(" -> ")
+}
+
+/// Shifts the relative references of the formula `source` by `(dx, dy)`, as when a formula is
+/// copied `dx` columns to the right and `dy` rows down
+pub fn shift_references(source: &str, dx: isize, dy: isize) -> String {
+    map_references(source, |reference| reference.shifted(dx, dy))
+}
+
+/// Removes the `$` anchors from the references of the formula `source`. Lua has no `$`, so
+/// formulas are evaluated without them.
+pub fn strip_anchors(source: &str) -> String {
+    map_references(source, |reference| {
+        Some(Reference {
+            absolute_x: false,
+            absolute_y: false,
+            ..reference
+        })
+    })
+}
+
+/// Direction in which cells are inserted or deleted
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Axis {
+    Row,
+    Column,
+}
+
+/// New position of the cell at `pos` after `count` rows or columns are inserted before `at`, or
+/// deleted starting from `at` if `count` is negative. Returns `None` if the cell is deleted.
+pub fn move_pos(pos: CellPos, axis: Axis, at: isize, count: isize) -> Option<CellPos> {
+    let mut pos = pos;
+    let coordinate = match axis {
+        Axis::Row => &mut pos.y,
+        Axis::Column => &mut pos.x,
+    };
+    if *coordinate < at {
+        return Some(pos);
+    }
+    if count < 0 && *coordinate < at - count {
+        return None;
+    }
+    *coordinate += count;
+    Some(pos)
+}
+
+/// Rewrites the references of the formula `source` so that they keep pointing at the same cells
+/// after the rows or columns are inserted or deleted as in `move_pos`. Anchored references are
+/// moved as well.
+pub fn move_references(source: &str, axis: Axis, at: isize, count: isize) -> String {
+    map_references(source, |reference| {
+        move_pos(reference.pos, axis, at, count).map(|pos| Reference { pos, ..reference })
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn parse_reference() {
+        let reference = Reference::parse("$B$3").unwrap();
+        assert_eq!(reference.pos, CellPos::from((1, 3)));
+        assert!(reference.absolute_x && reference.absolute_y);
+        assert_eq!(reference.to_string(), "$B$3");
+        assert_eq!(Reference::parse("A$10").unwrap().to_string(), "A$10");
+        assert!(Reference::parse("POSX").is_none());
+        assert!(Reference::parse("A1B").is_none());
+
+        let reference = Reference::parse("b$3").unwrap();
+        assert_eq!(reference.pos, CellPos::from((1, 3)));
+        assert_eq!(reference.shifted(1, 0).unwrap().to_string(), "c$3");
+    }
+
+    #[test]
+    fn address() {
+        assert_eq!(parse_address(" bb120 "), Some((27, 120).into()));
+        assert_eq!(parse_address("$B$3"), Some((1, 3).into()));
+        assert_eq!(parse_address("120"), None);
+    }
+
+    #[test]
+    fn find_references() {
+        assert_eq!(
+            references("=A1 + $B$2 * A1 .. \"C3\""),
+            vec![CellPos::from((0, 1)), CellPos::from((1, 2))]
+        );
+        assert!(references("A1").is_empty());
+        assert!(references("=abcdefghijklmnopqrstuvwxyz1").is_empty());
+        assert!(references("=A99999999999999999999").is_empty());
+        assert_eq!(
+            shift_references("=abcdefghijklmnopqrstuvwxyz1", 0, 1),
+            "=abcdefghijklmnopqrstuvwxyz1"
+        );
+    }
+
+    #[test]
+    fn cycles() {
+        let a1 = CellPos::from((0, 1));
+        let b3 = CellPos::from((1, 3));
+        let c0 = CellPos::from((2, 0));
+        let edges = HashMap::from_iter([(a1, vec![b3]), (b3, vec![c0, a1]), (c0, vec![])]);
+        assert_eq!(find_cycles(&edges), vec![vec![a1, b3, a1]]);
+        assert_eq!(format_cycle(&[a1, b3, a1]), "A1 -> B3 -> A1");
+
+        let edges = HashMap::from_iter([(a1, vec![a1]), (b3, vec![a1])]);
+        assert_eq!(find_cycles(&edges), vec![vec![a1, a1]]);
+        let edges = HashMap::from_iter([(a1, vec![b3]), (b3, vec![c0])]);
+        assert!(find_cycles(&edges).is_empty());
+    }
+
+    #[test]
+    fn shift() {
+        assert_eq!(shift_references("=A3+B3", 1, 2), "=B5+C5");
+        assert_eq!(shift_references("=$A$3+A$3+$A3", 1, 1), "=$A$3+B$3+$A4");
+        assert_eq!(shift_references("=A0", 0, -1), "=#REF!");
+        assert_eq!(shift_references("A3", 1, 1), "A3");
+        assert_eq!(strip_anchors("=$A$3+a$3 .. '$A$3'"), "=A3+a3 .. '$A$3'");
+    }
+
+    #[test]
+    fn skips_strings_and_fields() {
+        assert_eq!(
+            shift_references("=\"A1\" .. A1 .. t.A1 -- A1\n+ [[A1]]", 0, 1),
+            "=\"A1\" .. A2 .. t.A1 -- A1\n+ [[A1]]"
+        );
+        assert_eq!(
+            shift_references("=REL(1, 0) + 1e3", 1, 1),
+            "=REL(1, 0) + 1e3"
+        );
+    }
+
+    #[test]
+    fn skips_calls_and_declared_names() {
+        assert_eq!(shift_references("=LOG10(a1) + b2", 0, 1), "=LOG10(a2) + b3");
+        assert_eq!(
+            shift_references(
+                "=local x1 = A1 for i2 = 1, 3 do x1 = x1 + i2 end return x1",
+                0,
+                1
+            ),
+            "=local x1 = A2 for i2 = 1, 3 do x1 = x1 + i2 end return x1"
+        );
+        assert_eq!(
+            shift_references("=(function(a1) return a1 + b1 end)(c1)", 1, 0),
+            "=(function(a1) return a1 + c1 end)(d1)"
+        );
+    }
+
+    #[test]
+    fn insert_and_delete() {
+        let pos = CellPos::from((2, 5));
+        assert_eq!(move_pos(pos, Axis::Row, 5, 2), Some((2, 7).into()));
+        assert_eq!(move_pos(pos, Axis::Row, 6, 2), Some(pos));
+        assert_eq!(move_pos(pos, Axis::Column, 0, -2), Some((0, 5).into()));
+        assert_eq!(move_pos(pos, Axis::Column, 1, -2), None);
+
+        assert_eq!(move_references("=A1+$B$3", Axis::Row, 2, 1), "=A1+$B$4");
+        assert_eq!(move_references("=A1+C1", Axis::Column, 1, -1), "=A1+B1");
+        assert_eq!(move_references("=A1+B1", Axis::Column, 1, -1), "=A1+#REF!");
+        assert_eq!(move_references("=a5*$b$5", Axis::Row, 3, 2), "=a7*$b$7");
+    }
+}
@@ -0,0 +1,134 @@
+/// Continues the series started by the sources in `seeds` for `len` more cells. Numbers and
+/// `YYYY-MM-DD` dates with a constant step are series; at least two seeds are needed to find the
+/// step. Returns `None` if the seeds are not a series.
+pub fn extend(seeds: &[&str], len: usize) -> Option<Vec<String>> {
+    if seeds.len() < 2 {
+        return None;
+    }
+    extend_numbers(seeds, len).or_else(|| extend_dates(seeds, len))
+}
+
+fn constant_step(values: &[f64]) -> Option<f64> {
+    let step = values[1] - values[0];
+    values
+        .windows(2)
+        .all(|w| (w[1] - w[0] - step).abs() <= f64::EPSILON * w[1].abs().max(1.0) * 4.0)
+        .then_some(step)
+}
+
+fn extend_numbers(seeds: &[&str], len: usize) -> Option<Vec<String>> {
+    let values = seeds
+        .iter()
+        .map(|seed| seed.trim().parse::<f64>().ok().filter(|v| v.is_finite()))
+        .collect::<Option<Vec<_>>>()?;
+    let step = constant_step(&values)?;
+    // Extended values are written with as many decimals as the most precise seed
+    let decimals = seeds
+        .iter()
+        .map(|seed| seed.trim().split_once('.').map_or(0, |(_, d)| d.len()))
+        .max()
+        .unwrap_or(0);
+    let last = values[values.len() - 1];
+    Some(
+        (1..=len)
+            .map(|i| format!("{:.decimals$}", last + step * i as f64))
+            .collect(),
+    )
+}
+
+fn extend_dates(seeds: &[&str], len: usize) -> Option<Vec<String>> {
+    let days = seeds
+        .iter()
+        .map(|seed| parse_date(seed.trim()))
+        .collect::<Option<Vec<_>>>()?;
+    let step = days[1] - days[0];
+    if days.windows(2).any(|w| w[1] - w[0] != step) {
+        return None;
+    }
+    let last = days[days.len() - 1];
+    Some(
+        (1..=len as i64)
+            .map(|i| format_date(last + step * i))
+            .collect(),
+    )
+}
+
+/// Days since 1970-01-01 of a `YYYY-MM-DD` date
+fn parse_date(s: &str) -> Option<i64> {
+    let mut parts = s.split('-');
+    let (year, month, day) = (parts.next()?, parts.next()?, parts.next()?);
+    if parts.next().is_some() || year.len() != 4 || month.len() != 2 || day.len() != 2 {
+        return None;
+    }
+    let (year, month, day) = (
+        year.parse::<i64>().ok()?,
+        month.parse::<i64>().ok()?,
+        day.parse::<i64>().ok()?,
+    );
+    let days = days_from_civil(year, month, day);
+    (civil_from_days(days) == (year, month, day)).then_some(days)
+}
+
+fn format_date(days: i64) -> String {
+    let (year, month, day) = civil_from_days(days);
+    format!("{year:04}-{month:02}-{day:02}")
+}
+
+// Conversions between the proleptic Gregorian calendar and day numbers, see
+// https://howardhinnant.github.io/date_algorithms.html
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let day_of_year = (153 * (month + if month > 2 { -3 } else { 9 }) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146097 + day_of_era - 719468
+}
+
+fn civil_from_days(days: i64) -> (i64, i64, i64) {
+    let days = days + 719468;
+    let era = days.div_euclid(146097);
+    let day_of_era = days - era * 146097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year, month, day)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn numbers() {
+        assert_eq!(
+            extend(&["1", "2", "3"], 2),
+            Some(vec!["4".into(), "5".into()])
+        );
+        assert_eq!(
+            extend(&["10", "7.5"], 2),
+            Some(vec!["5.0".into(), "2.5".into()])
+        );
+        assert_eq!(extend(&["0.1", "0.2"], 1), Some(vec!["0.3".into()]));
+        assert_eq!(extend(&["1", "2", "4"], 1), None);
+        assert_eq!(extend(&["1"], 1), None);
+        assert_eq!(extend(&["=A1", "=A2"], 1), None);
+    }
+
+    #[test]
+    fn dates() {
+        assert_eq!(
+            extend(&["2024-02-27", "2024-02-28"], 2),
+            Some(vec!["2024-02-29".into(), "2024-03-01".into()])
+        );
+        assert_eq!(
+            extend(&["2023-12-25", "2024-01-01"], 1),
+            Some(vec!["2024-01-08".into()])
+        );
+        assert_eq!(extend(&["2023-02-29", "2023-03-01"], 1), None);
+    }
+}
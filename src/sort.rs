@@ -0,0 +1,160 @@
+use std::cmp::Ordering;
+
+use bight::evaluator::TableValue;
+
+use crate::reference::parse_address;
+
+/// A column to sort rows by
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SortKey {
+    pub x: isize,
+    pub descending: bool,
+}
+
+impl SortKey {
+    /// Parses a column's letters optionally followed by `!` for descending order, e.g. `B!` or
+    /// `b!`
+    pub fn parse(s: &str) -> Option<Self> {
+        let (column, descending) = match s.strip_suffix('!') {
+            Some(column) => (column, true),
+            None => (s, false),
+        };
+        if !column.chars().all(|c| c.is_ascii_alphabetic()) {
+            return None;
+        }
+        let pos = parse_address(&format!("{column}0"))?;
+        Some(Self {
+            x: pos.x,
+            descending,
+        })
+    }
+}
+
+/// The value as a number if it is one or is text holding one. Literals are evaluated as text, so
+/// typed and loaded numbers are `Text("10")`.
+fn as_number(value: &TableValue) -> Option<f64> {
+    match value {
+        TableValue::Number(n) => Some(*n),
+        TableValue::Text(text) => text.trim().parse().ok().filter(|n: &f64| n.is_finite()),
+        _ => None,
+    }
+}
+
+/// Order of cell values when sorting in ascending order: numbers by value, then text (ignoring
+/// case), then errors
+fn compare_values(a: &TableValue, b: &TableValue) -> Ordering {
+    use TableValue::*;
+    let rank = |v: &TableValue| match v {
+        _ if as_number(v).is_some() => 0,
+        Number(_) | Text(_) => 1,
+        Err(_) => 2,
+        Empty => 3,
+    };
+    match (as_number(a), as_number(b), a, b) {
+        (Some(a), Some(b), _, _) => a.total_cmp(&b),
+        (None, None, Text(a), Text(b)) => a
+            .to_lowercase()
+            .cmp(&b.to_lowercase())
+            .then_with(|| a.cmp(b)),
+        _ => rank(a).cmp(&rank(b)),
+    }
+}
+
+/// Compares two rows by the values of their key columns, given in the order of `keys`. Empty
+/// cells are placed last in both ascending and descending order.
+pub fn compare_rows(keys: &[SortKey], a: &[TableValue], b: &[TableValue]) -> Ordering {
+    keys.iter()
+        .zip(a.iter().zip(b))
+        .map(|(key, (a, b))| match (a, b) {
+            (TableValue::Empty, TableValue::Empty) => Ordering::Equal,
+            (TableValue::Empty, _) => Ordering::Greater,
+            (_, TableValue::Empty) => Ordering::Less,
+            _ if key.descending => compare_values(b, a),
+            _ => compare_values(a, b),
+        })
+        .find(|ordering| ordering.is_ne())
+        .unwrap_or(Ordering::Equal)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn text(s: &str) -> TableValue {
+        TableValue::Text(s.into())
+    }
+
+    #[test]
+    fn parse_key() {
+        assert_eq!(
+            SortKey::parse("B!"),
+            Some(SortKey {
+                x: 1,
+                descending: true
+            })
+        );
+        assert_eq!(SortKey::parse("A").map(|key| key.x), Some(0));
+        assert_eq!(SortKey::parse("c").map(|key| key.x), Some(2));
+        assert_eq!(SortKey::parse("A1"), None);
+    }
+
+    #[test]
+    fn order() {
+        let ascending = [SortKey {
+            x: 0,
+            descending: false,
+        }];
+        let descending = [SortKey {
+            x: 0,
+            descending: true,
+        }];
+        let mut rows = [
+            vec![text("b")],
+            vec![TableValue::Empty],
+            vec![TableValue::Number(10.0)],
+            vec![text("A")],
+            vec![TableValue::Number(9.0)],
+        ];
+        rows.sort_by(|a, b| compare_rows(&ascending, a, b));
+        let shown: Vec<String> = rows.iter().map(|row| row[0].to_string()).collect();
+        assert_eq!(shown, ["9", "10", "A", "b", ""]);
+        rows.sort_by(|a, b| compare_rows(&descending, a, b));
+        let shown: Vec<String> = rows.iter().map(|row| row[0].to_string()).collect();
+        assert_eq!(shown, ["b", "A", "10", "9", ""]);
+    }
+
+    #[test]
+    fn text_numbers() {
+        let ascending = [SortKey {
+            x: 0,
+            descending: false,
+        }];
+        let mut rows = [
+            vec![text("10")],
+            vec![text("x")],
+            vec![text("9")],
+            vec![TableValue::Number(9.5)],
+            vec![text(" -2.5 ")],
+        ];
+        rows.sort_by(|a, b| compare_rows(&ascending, a, b));
+        let shown: Vec<String> = rows.iter().map(|row| row[0].to_string()).collect();
+        assert_eq!(shown, [" -2.5 ", "9", "9.5", "10", "x"]);
+    }
+
+    #[test]
+    fn multiple_keys() {
+        let keys = [
+            SortKey {
+                x: 0,
+                descending: false,
+            },
+            SortKey {
+                x: 1,
+                descending: true,
+            },
+        ];
+        let a = [TableValue::Number(1.0), TableValue::Number(1.0)];
+        let b = [TableValue::Number(1.0), TableValue::Number(2.0)];
+        assert_eq!(compare_rows(&keys, &a, &b), Ordering::Greater);
+    }
+}
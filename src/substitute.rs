@@ -0,0 +1,139 @@
+/// Which cells a substitution applies to
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SourceKind {
+    All,
+    Formulas,
+    Literals,
+}
+
+impl SourceKind {
+    pub fn matches(self, source: &str) -> bool {
+        match self {
+            SourceKind::All => true,
+            SourceKind::Formulas => source.starts_with('='),
+            SourceKind::Literals => !source.starts_with('='),
+        }
+    }
+}
+
+/// A parsed `/pattern/replacement/[flags]` substitution. The pattern is a vim regex.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Substitution {
+    pub pattern: String,
+    pub replacement: String,
+    /// Replace all occurrences in a source instead of the first one (`g`)
+    pub global: bool,
+    /// Ignore case (`i`) or match case (`I`), 'ignorecase' is used if not set
+    pub ignore_case: Option<bool>,
+    /// Only count the cells that would change (`n`)
+    pub count_only: bool,
+    /// Only formulas (`f`) or only literals (`l`)
+    pub kind: SourceKind,
+}
+
+impl Substitution {
+    /// Parses a substitution from the start of `s`. Any non-alphanumeric character other than a
+    /// space, `\` or `"` may be used as the delimiter, like in Vim's `:s`, and is escaped with `\`
+    /// inside of the pattern and the replacement. Returns the substitution and the rest of `s`
+    /// after the flags.
+    pub fn parse(s: &str) -> Result<(Self, &str), String> {
+        let s = s.trim_start();
+        let delimiter = s
+            .chars()
+            .next()
+            .filter(|c| !c.is_alphanumeric() && !c.is_whitespace() && *c != '\\' && *c != '"')
+            .ok_or_else(|| String::from("Expected /pattern/replacement/[flags]"))?;
+        let rest = &s[delimiter.len_utf8()..];
+        let (pattern, rest) = split_part(rest, delimiter);
+        let Some(rest) = rest else {
+            return Err(String::from("Missing replacement"));
+        };
+        if pattern.is_empty() {
+            return Err(String::from("Empty pattern"));
+        }
+        let (replacement, rest) = split_part(rest, delimiter);
+        let rest = rest.unwrap_or("");
+        let flags_end = rest.find(char::is_whitespace).unwrap_or(rest.len());
+        let (flags, rest) = rest.split_at(flags_end);
+
+        let mut substitution = Self {
+            pattern,
+            replacement,
+            global: false,
+            ignore_case: None,
+            count_only: false,
+            kind: SourceKind::All,
+        };
+        for flag in flags.chars() {
+            match flag {
+                'g' => substitution.global = true,
+                'i' => substitution.ignore_case = Some(true),
+                'I' => substitution.ignore_case = Some(false),
+                'n' => substitution.count_only = true,
+                'f' => substitution.kind = SourceKind::Formulas,
+                'l' => substitution.kind = SourceKind::Literals,
+                _ => return Err(format!("Invalid flag {flag}")),
+            }
+        }
+        Ok((substitution, rest.trim()))
+    }
+    /// The pattern with the case flag applied in vim regex syntax
+    pub fn vim_pattern(&self) -> String {
+        match self.ignore_case {
+            Some(true) => format!("\\c{}", self.pattern),
+            Some(false) => format!("\\C{}", self.pattern),
+            None => self.pattern.clone(),
+        }
+    }
+}
+
+/// Splits `s` at the first unescaped `delimiter`, unescaping escaped delimiters. The second part
+/// is `None` if there is no delimiter.
+fn split_part(s: &str, delimiter: char) -> (String, Option<&str>) {
+    let mut part = String::new();
+    let mut chars = s.char_indices();
+    while let Some((i, c)) = chars.next() {
+        if c == delimiter {
+            return (part, Some(&s[i + c.len_utf8()..]));
+        }
+        if c == '\\' {
+            match chars.next() {
+                Some((_, next)) if next == delimiter => part.push(next),
+                Some((_, next)) => {
+                    part.push(c);
+                    part.push(next);
+                }
+                None => part.push(c),
+            }
+        } else {
+            part.push(c);
+        }
+    }
+    (part, None)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn parse() {
+        let (substitution, rest) = Substitution::parse("/A\\d/B\\//gf A0_C10").unwrap();
+        assert_eq!(substitution.pattern, "A\\d");
+        assert_eq!(substitution.replacement, "B/");
+        assert!(substitution.global);
+        assert_eq!(substitution.kind, SourceKind::Formulas);
+        assert_eq!(rest, "A0_C10");
+
+        let (substitution, rest) = Substitution::parse("#a b#c").unwrap();
+        assert_eq!(substitution.pattern, "a b");
+        assert_eq!(substitution.replacement, "c");
+        assert!(!substitution.global);
+        assert_eq!(rest, "");
+
+        assert!(Substitution::parse("/a").is_err());
+        assert!(Substitution::parse("//b/").is_err());
+        assert!(Substitution::parse("/a/b/x").is_err());
+        assert!(Substitution::parse("\"a\"b\"").is_err());
+    }
+}
@@ -4,11 +4,14 @@ pub use api::make as make_api;
 
 use bight::table::cell::CellPos;
 
-use crate::editor::{CELL_UNIT_WIDTH, CELL_WIDTH};
+use crate::{
+    editor::{Editor, Viewport},
+    reference::parse_address,
+};
 
 use nvim_oxi::{
     self as nvim,
-    api::{Buffer, opts::EchoOpts},
+    api::{Buffer, Window, opts::EchoOpts},
 };
 
 pub fn get_cursor() -> (usize, usize) {
@@ -36,43 +39,96 @@ pub fn set_cursor(line: usize, col: usize) {
     nvim::api::get_current_win().set_cursor(line, col).unwrap();
 }
 
-pub fn cell_pos((cursorx, cursory): (usize, usize)) -> CellPos {
-    (((cursorx) / CELL_UNIT_WIDTH) as isize, cursory as isize - 1).into()
+pub fn cell_pos(viewport: &Viewport, (cursorx, cursory): (usize, usize)) -> CellPos {
+    (
+        viewport.column_at(cursorx),
+        viewport.origin.y + cursory as isize - 1,
+    )
+        .into()
 }
 pub fn current_cell_pos() -> CellPos {
     let (row, col) = get_cursor();
-    cell_pos((col, row))
+    cell_pos(&current_viewport(), (col, row))
 }
 
-pub fn cursor_position(pos: CellPos) -> (usize, usize) {
-    let x: usize = pos.x.try_into().unwrap_or_default();
-    let y: usize = pos.y.try_into().unwrap_or_default();
-    (y + 1, x * CELL_UNIT_WIDTH)
+pub fn cursor_position(viewport: &Viewport, pos: CellPos) -> (usize, usize) {
+    let y: usize = (pos.y - viewport.origin.y).try_into().unwrap_or_default();
+    (y + 1, viewport.column_offset(pos.x))
 }
 
-pub fn normalize_cursor_position(line: usize, col: usize) -> (usize, usize) {
-    (line, col - col % CELL_UNIT_WIDTH)
+pub fn normalize_cursor_position(viewport: &Viewport, line: usize, col: usize) -> (usize, usize) {
+    (line, viewport.column_offset(viewport.column_at(col)))
+}
+
+/// Viewport of the editor attached to the current buffer
+pub fn current_viewport() -> Viewport {
+    Editor::of_existing_buffer(None)
+        .map(|editor| editor.viewport())
+        .unwrap_or_default()
+}
+
+/// Width of the text area (in columns, excluding number, sign and status columns) and height (in
+/// lines) of the current window
+pub fn window_size() -> (usize, usize) {
+    let window = nvim::api::get_current_win();
+    let width = window.get_width().unwrap() as usize;
+    let height = window.get_height().unwrap() as usize;
+    (width.saturating_sub(text_offset(&window)), height.max(1))
+}
+
+/// Width of the columns left of the text of `window`: the number, sign, fold and status columns
+pub fn text_offset(window: &Window) -> usize {
+    lua::nvim_mlua()
+        .load("return vim.fn.getwininfo(...)[1].textoff")
+        .call::<usize>(window.handle())
+        .unwrap_or_default()
+}
+
+/// Scrolls the current editor's viewport so that `pos` is visible and returns the new viewport
+fn scroll_to_cell_pos(pos: CellPos) -> Viewport {
+    match Editor::of_existing_buffer(None) {
+        Some(editor) => {
+            editor.scroll_to(pos);
+            editor.viewport()
+        }
+        None => Viewport::default(),
+    }
 }
 
 pub fn set_cursor_to_cell_pos(pos: CellPos) {
-    let (line, col) = cursor_position(pos);
+    let viewport = scroll_to_cell_pos(pos);
+    let (line, col) = cursor_position(&viewport, pos);
 
     set_cursor(line, col);
 }
 pub fn set_cursor_to_cell_pos_visual(pos: CellPos) {
-    let (line, mut col) = cursor_position(pos);
-    col += CELL_WIDTH;
+    let viewport = scroll_to_cell_pos(pos);
+    let (line, mut col) = cursor_position(&viewport, pos);
+    col += viewport.widths.get(pos.x);
 
     set_cursor(line, col);
 }
 
+/// Moves the cursor to the cell at the A1 `address`. Reports invalid addresses.
+pub fn goto_address(address: &str) {
+    match parse_address(address) {
+        Some(pos) => set_cursor_to_cell_pos(pos),
+        None => notify_err(&format!("Invalid cell address {address}")),
+    }
+}
+
 pub fn normalize_cursor() {
     let (line, col) = get_cursor();
-    let (line, col) = normalize_cursor_position(line, col);
+    let (line, col) = normalize_cursor_position(&current_viewport(), line, col);
 
     set_cursor(line, col);
 }
 
+/// Count given to the current mapping (`v:count1`)
+pub fn count() -> isize {
+    nvim::api::get_vvar::<i64>("count1").map_or(1, |count| count as isize)
+}
+
 pub fn move_cells(x: isize, y: isize) {
     let mut pos = current_cell_pos();
     pos.x += x;
@@ -169,6 +225,39 @@ pub fn get_buffer_line(buffer: &Buffer, line: usize) -> String {
         .to_string()
 }
 
+/// Makes the buffer's lines equal to `lines`, rewriting only the runs of lines that differ
+pub fn set_changed_lines(buffer: &mut Buffer, lines: Vec<String>) {
+    let old: Vec<String> = buffer
+        .get_lines(.., false)
+        .unwrap()
+        .map(|line| line.to_string())
+        .collect();
+    let mut i = 0;
+    while i < lines.len() {
+        if old.get(i) == Some(&lines[i]) {
+            i += 1;
+            continue;
+        }
+        let start = i;
+        while i < lines.len() && old.get(i) != Some(&lines[i]) {
+            i += 1;
+        }
+        let end = i.min(old.len());
+        buffer
+            .set_lines(
+                start..end,
+                false,
+                lines[start..i].iter().map(String::as_str),
+            )
+            .unwrap();
+    }
+    if old.len() > lines.len() {
+        buffer
+            .set_lines(lines.len()..old.len(), false, std::iter::empty::<&str>())
+            .unwrap();
+    }
+}
+
 pub fn get_buffer_as_string(buffer: &Buffer) -> String {
     buffer
         .get_lines(.., false)
@@ -194,15 +283,33 @@ mod lua {
         Object::from(Function::from_fn(move |()| f()))
     }
     pub fn get_as_bool(dict: &Dictionary, key: &str) -> bool {
+        get_as_bool_or(dict, key, false)
+    }
+    pub fn get_as_bool_or(dict: &Dictionary, key: &str, default: bool) -> bool {
         let Some(x) = dict.get(key) else {
-            return false;
+            return default;
         };
         match x.kind() {
-            ObjectKind::Nil => false,
+            ObjectKind::Nil => default,
             ObjectKind::Boolean => unsafe { x.as_boolean_unchecked() },
             _ => true,
         }
     }
+    pub fn get_as_integer(dict: &Dictionary, key: &str) -> Option<i64> {
+        let x = dict.get(key)?;
+        match x.kind() {
+            ObjectKind::Integer => Some(unsafe { x.as_integer_unchecked() }),
+            ObjectKind::Float => Some(unsafe { x.as_float_unchecked() } as i64),
+            _ => None,
+        }
+    }
+    pub fn get_as_dictionary<'a>(dict: &'a Dictionary, key: &str) -> Option<&'a Dictionary> {
+        let x = dict.get(key)?;
+        match x.kind() {
+            ObjectKind::Dictionary => Some(unsafe { x.as_dictionary_unchecked() }),
+            _ => None,
+        }
+    }
 
     pub fn pop_error<T>(msg: impl ToString) -> nvim::lua::Error {
         LuaError::pop_error(type_name::<T>(), msg.to_string())
@@ -213,4 +320,13 @@ mod lua {
             nvim::lua::with_state(|state| mlua::Lua::get_or_init_from_ptr(state as *mut _).clone())
         }
     }
+    /// The vimscript function `name` from `vim.fn`
+    pub fn vim_fn(name: &str) -> mlua::Function {
+        nvim_mlua()
+            .globals()
+            .get::<mlua::Table>("vim")
+            .and_then(|vim| vim.get::<mlua::Table>("fn"))
+            .and_then(|f| f.get::<mlua::Function>(name))
+            .unwrap()
+    }
 }
@@ -1,5 +1,7 @@
 use super::*;
-use nvim_oxi::Dictionary;
+use nvim_oxi::{Array, Dictionary, Object};
+
+use crate::editor::{Editor, cell_info, statuscolumn};
 
 pub fn make() -> Dictionary {
     let mut api = Dictionary::new();
@@ -10,6 +12,42 @@ pub fn make() -> Dictionary {
             (pos.x, pos.y)
         }),
     );
+    api.insert(
+        "get_cell_info",
+        fn_object(|()| {
+            let editor = Editor::of_existing_buffer(None)?;
+            let pos = current_cell_pos();
+            editor.evaluate();
+            Some(Dictionary::from_iter([
+                ("address", Object::from(pos.to_string())),
+                ("x", Object::from(pos.x as i64)),
+                ("y", Object::from(pos.y as i64)),
+                ("source", Object::from(editor.get_source(pos))),
+                ("value", Object::from(editor.get_value(pos))),
+                ("is_error", Object::from(editor.is_err(pos))),
+            ]))
+        }),
+    );
+    api.insert("cell_info", unit_fn_object(cell_info));
+    api.insert(
+        "find",
+        fn_object(|pattern: String| {
+            let Some(editor) = Editor::of_existing_buffer(None) else {
+                return Array::new();
+            };
+            let matches = editor.find(&pattern);
+            editor.set_matches(matches.clone());
+            Array::from_iter(matches.into_iter().map(|pos| {
+                Dictionary::from_iter([
+                    ("address", Object::from(pos.to_string())),
+                    ("x", Object::from(pos.x as i64)),
+                    ("y", Object::from(pos.y as i64)),
+                    ("source", Object::from(editor.get_source(pos))),
+                    ("value", Object::from(editor.get_value(pos))),
+                ])
+            }))
+        }),
+    );
     api.insert(
         "set_cursor_to_cell_pos",
         fn_object(|(x, y)| {
@@ -17,6 +55,12 @@ pub fn make() -> Dictionary {
             set_cursor_to_cell_pos(pos);
         }),
     );
+    api.insert(
+        "goto_cell",
+        fn_object(|address: String| {
+            goto_address(&address);
+        }),
+    );
     api.insert(
         "normalize_cursor",
         fn_object(|()| {
@@ -33,6 +77,7 @@ pub fn make() -> Dictionary {
     api.insert("move_right", unit_fn_object(move_right));
     api.insert("move_up", unit_fn_object(move_up));
     api.insert("move_down", unit_fn_object(move_down));
+    api.insert("statuscolumn", unit_fn_object(statuscolumn));
 
     api
 }